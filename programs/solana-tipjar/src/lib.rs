@@ -1,26 +1,117 @@
+// Anchor 0.31's #[program] output still calls AccountInfo::realloc, and it is emitted
+// beside `tipjar` rather than inside it, so an allow on the module doesn't reach it
+#![allow(deprecated)]
+
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::system_program;
+use anchor_spl::associated_token::{get_associated_token_address_with_program_id, AssociatedToken};
+use anchor_spl::memo::{self, BuildMemo, Memo};
+use anchor_spl::token::Token;
+use anchor_spl::token_2022::spl_token_2022::extension::{
+    memo_transfer, transfer_fee::TransferFeeAmount, BaseStateWithExtensions, StateWithExtensions,
+};
+use anchor_spl::token_2022::{spl_token_2022, Token2022};
+use anchor_spl::token_interface::{
+    self, CloseAccount, HarvestWithheldTokensToMint, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
 use crate::state::*;
 
 declare_id!("6U7ezSr7phBBojC5PRUuutUNFpMiDxUxXeiKfjTZduMs");
 
 // Import state module to access TipJar and Tip structs
+pub mod merkle;
 pub mod state;
 
 #[program]
 pub mod tipjar {
     use super::*;
 
+    /// Creates the global config; only the program's upgrade authority can call this
+    pub fn initialize_config(ctx: Context<InitializeConfig>, fee_bps: u16, treasury: Pubkey) -> Result<()> {
+        require!(fee_bps <= ProgramConfig::MAX_FEE_BPS, TipJarError::FeeTooHigh);
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.fee_bps = fee_bps;
+        config.treasury = treasury;
+        config.bump = ctx.bumps.config;
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            fee_bps,
+            treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Changes the protocol fee taken from SOL tips, up to `ProgramConfig::MAX_FEE_BPS`
+    pub fn set_protocol_fee(ctx: Context<UpdateConfig>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= ProgramConfig::MAX_FEE_BPS, TipJarError::FeeTooHigh);
+
+        let config = &mut ctx.accounts.config;
+        config.fee_bps = fee_bps;
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            fee_bps,
+            treasury: config.treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Changes the wallet that receives protocol fees
+    pub fn set_fee_treasury(ctx: Context<UpdateConfig>, treasury: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.treasury = treasury;
+
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            fee_bps: config.fee_bps,
+            treasury,
+        });
+
+        Ok(())
+    }
+
+    /// Hands the config to a new admin
+    pub fn set_config_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = new_admin;
+
+        emit!(ConfigUpdated {
+            admin: new_admin,
+            fee_bps: config.fee_bps,
+            treasury: config.treasury,
+        });
+
+        Ok(())
+    }
+
     /// Creates a new tip jar with the provided details
     /// Takes description, category, and goal amount
+    /// The jar address is derived from the creator's next free registry index,
+    /// so one wallet can run any number of jars
     pub fn initialize_tipjar(ctx: Context<InitializeTipJar>, description: String, category: String, goal: u64) -> Result<()> {
         // Validate input parameters
         require!(goal > 0, TipJarError::InvalidGoal);
         require!(description.len() <= 200, TipJarError::DescriptionTooLong);
         require!(category.len() <= 100, TipJarError::CategoryTooLong);
         
+        let registry = &mut ctx.accounts.registry;
         let tip_jar = &mut ctx.accounts.tipjar;
         let user = &ctx.accounts.user;
+
+        // First jar for this wallet, set up the registry
+        if registry.owner == Pubkey::default() {
+            registry.owner = user.key();
+            registry.bump = ctx.bumps.registry;
+        }
+
+        // Track the new jar so clients can list it without scanning
+        require!(registry.jars.len() < OwnerRegistry::MAX_JARS, TipJarError::TooManyTipJars);
+        registry.jars.push(tip_jar.key());
         
         // Initialize TipJar fields
         tip_jar.description = description;
@@ -29,415 +120,3502 @@ pub mod tipjar {
         tip_jar.total_received = 0;
         tip_jar.is_active = true;
         tip_jar.owner = user.key();
-        tip_jar.bump = *ctx.bumps.get("tipjar").unwrap();
+        tip_jar.creator = user.key();
+        tip_jar.index = registry.next_index;
+        tip_jar.withdrawal_policy = WithdrawalPolicy::default();
+        tip_jar.bump = ctx.bumps.tipjar;
+
+        // Indexes are never reused, even after a jar is closed
+        registry.next_index += 1;
         
         Ok(())
     }
 
     /// Sends a tip to a tip jar with optional message and visibility setting
-    pub fn send_tip(ctx: Context<SendTip>, amount: u64, visibility: Visibility, memo: String) -> Result<()> {
+    /// Jars in split-on-receive mode take the split recipients as writable
+    /// remaining accounts, in split table order, and pay them directly
+    pub fn send_tip<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendTip<'info>>,
+        amount: u64,
+        visibility: Visibility,
+        memo: String,
+        proof: Option<Vec<[u8; 32]>>,
+    ) -> Result<()> {
         // Validate inputs
         require!(amount > 0, TipJarError::InvalidAmount);
         require!(memo.len() <= 100, TipJarError::MemoTooLong);
 
         let tip_jar = &mut ctx.accounts.tipjar;
         let sender = &ctx.accounts.sender;
+        let config = &ctx.accounts.config;
+        tip_jar.check_tip_amount(amount)?;
+
+        require!(ctx.accounts.blocked_sender.data_is_empty(), TipJarError::SenderBlocked);
+
+        // Check privacy settings, members prove themselves with a PDA entry or a Merkle proof
+        let allowlisted = ctx.accounts.allowlist_entry.is_some()
+            || proof.is_some_and(|proof| tip_jar.in_allowlist_tree(&sender.key(), &proof));
+        require!(
+            tip_jar.accepts_tips_from(&sender.key(), allowlisted),
+            TipJarError::NotAllowlisted
+        );
         
         // Check if tip jar is active
         if !tip_jar.is_active {
-            // Emit an event for the refund
-            emit!(TipRefunded {
+            // Reject outright unless the owner opted into holding tips for refund
+            require!(
+                tip_jar.inactive_policy == InactiveTipPolicy::Escrow,
+                TipJarError::InactiveTipJar
+            );
+
+            // No tip is stored, so the sequence slot must stay free for the next tip
+            require!(ctx.accounts.tip_record.is_none(), TipJarError::InvalidTipRecord);
+            // Held tips aren't contributions until they reach the jar
+            require!(ctx.accounts.contribution.is_none(), TipJarError::InvalidContribution);
+
+            let escrow = ctx
+                .accounts
+                .refund_escrow
+                .as_mut()
+                .ok_or(TipJarError::MissingRefundEscrow)?;
+
+            // Hold the SOL in the sender's escrow instead of the jar
+            let ix = anchor_lang::solana_program::system_instruction::transfer(
+                &sender.key(),
+                &escrow.key(),
+                amount,
+            );
+            anchor_lang::solana_program::program::invoke(
+                &ix,
+                &[
+                    sender.to_account_info(),
+                    escrow.to_account_info(),
+                ],
+            )?;
+
+            escrow.tipjar = tip_jar.key();
+            escrow.sender = sender.key();
+            escrow.amount += amount;
+            escrow.bump = ctx.bumps.refund_escrow.ok_or(TipJarError::MissingRefundEscrow)?;
+
+            emit!(TipEscrowed {
                 tipjar: tip_jar.key(),
-                sender: sender.key(),
+                sender: visibility.shown_sender(sender.key()),
                 lamports: amount,
                 timestamp: Clock::get()?.unix_timestamp as u64,
             });
-            
+
             return Ok(());
         }
 
-        // Check privacy settings
-        if tip_jar.is_private {
-            // Block if the sender is not the owner
-            require_keys_eq!(sender.key(), tip_jar.owner, TipJarError::Unauthorized);
+        // All-or-nothing campaigns hold each tip as a refundable pledge
+        if tip_jar.crowdfunding {
+            let now = Clock::get()?.unix_timestamp;
+            require!(tip_jar.before_deadline(now), TipJarError::CampaignEnded);
+            // Pledges stay in the jar, so they can't be split or charged a fee yet
+            require!(ctx.remaining_accounts.is_empty(), TipJarError::InvalidSplitRecipient);
+
+            let escrow = ctx
+                .accounts
+                .refund_escrow
+                .as_mut()
+                .ok_or(TipJarError::MissingRefundEscrow)?;
+            escrow.tipjar = tip_jar.key();
+            escrow.sender = sender.key();
+            escrow.bump = ctx.bumps.refund_escrow.ok_or(TipJarError::MissingRefundEscrow)?;
+
+            let ix = anchor_lang::solana_program::system_instruction::transfer(
+                &sender.key(),
+                &tip_jar.key(),
+                amount,
+            );
+            anchor_lang::solana_program::program::invoke(
+                &ix,
+                &[
+                    sender.to_account_info(),
+                    tip_jar.to_account_info(),
+                ],
+            )?;
+
+            store_tip(
+                tip_jar,
+                ctx.accounts.tip_record.as_mut(),
+                ctx.bumps.tip_record,
+                sender.key(),
+                ctx.accounts.tip_history.as_ref(),
+                Tip {
+                    sender: visibility.shown_sender(sender.key()),
+                    amount,
+                    mint: None,
+                    visibility,
+                    memo: memo.clone(),
+                    timestamp: now as u64,
+                },
+            )?;
+
+            tip_jar.record_pledge(escrow, amount, visibility);
+            check_milestones(tip_jar);
+
+            record_contribution(
+                ctx.accounts.contribution.as_mut(),
+                ctx.bumps.contribution,
+                tip_jar.key(),
+                sender.key(),
+                amount,
+                visibility,
+                now,
+            )?;
+
+            emit!(TipSent {
+                sender: visibility.shown_sender(sender.key()),
+                receiver: tip_jar.key(),
+                amount,
+                fee: 0,
+                mint: None,
+                memo,
+                visibility,
+            });
+
+            if tip_jar.pledged_total >= tip_jar.goal {
+                emit!(GoalReached {
+                    tipjar: tip_jar.key(),
+                    goal: tip_jar.goal,
+                    total_received: tip_jar.pledged_total,
+                });
+            }
+
+            return Ok(());
         }
 
-        // Create the new tip
-        let new_tip = Tip {
-            sender: sender.key(),
-            amount,
+        // Nothing is held for refund, so an escrow created here would be left empty
+        require!(ctx.accounts.refund_escrow.is_none(), TipJarError::InvalidRefundEscrow);
+
+        // Fee, history, payout and events are shared with subscription payments
+        let now = Clock::get()?.unix_timestamp;
+        let credited = deliver_sol_tip(
+            tip_jar,
+            &sender.to_account_info(),
+            config,
+            &ctx.accounts.treasury.to_account_info(),
+            ctx.accounts.tip_record.as_mut(),
+            ctx.bumps.tip_record,
+            sender.key(),
+            ctx.accounts.tip_history.as_ref(),
+            ctx.remaining_accounts,
+            Tip {
+                sender: visibility.shown_sender(sender.key()),
+                amount,
+                mint: None,
+                visibility,
+                memo,
+                timestamp: now as u64,
+            },
+        )?;
+
+        record_contribution(
+            ctx.accounts.contribution.as_mut(),
+            ctx.bumps.contribution,
+            tip_jar.key(),
+            sender.key(),
+            credited,
             visibility,
-            memo: memo.clone(),
-            timestamp: Clock::get()?.unix_timestamp as u64,
-        };
-        
-        // Store the tip using circular buffer to maintain fixed size history
-        if tip_jar.tips_history.len() < TipJar::MAX_TIP_HISTORY_LEN {
-            tip_jar.tips_history.push(new_tip.clone());
-        } else {
-            let index = (tip_jar.last_tip_index as usize) % TipJar::MAX_TIP_HISTORY_LEN;
-            tip_jar.tips_history[index] = new_tip.clone();
-            tip_jar.last_tip_index = ((tip_jar.last_tip_index as usize + 1) % TipJar::MAX_TIP_HISTORY_LEN) as u16;
-        }
-        
-        // Increment total tips counter
-        tip_jar.total_tips_count += 1;
+            now,
+        )
+    }
 
-        // Transfer SOL from sender to tip jar using the Solana System Program
-        let ix = anchor_lang::solana_program::system_instruction::transfer(
-            &sender.key(),
-            &tip_jar.key(),
-            amount,
+    /// Sends an SPL token tip into the jar's associated token vault
+    /// Works with both the classic token program and Token-2022 mints
+    pub fn send_token_tip(
+        ctx: Context<SendTokenTip>,
+        amount: u64,
+        visibility: Visibility,
+        memo: String,
+        proof: Option<Vec<[u8; 32]>>,
+    ) -> Result<()> {
+        // Validate inputs
+        require!(amount > 0, TipJarError::InvalidAmount);
+        require!(memo.len() <= 100, TipJarError::MemoTooLong);
+
+        let tip_jar = &mut ctx.accounts.tipjar;
+        let sender = &ctx.accounts.sender;
+        let mint = ctx.accounts.mint.key();
+
+        // Token tips are rejected outright when the jar is paused, and while an
+        // all-or-nothing campaign runs since they couldn't be refunded
+        require!(tip_jar.is_active, TipJarError::InactiveTipJar);
+        require!(!tip_jar.crowdfunding, TipJarError::PledgeRequired);
+
+        require!(ctx.accounts.blocked_sender.data_is_empty(), TipJarError::SenderBlocked);
+
+        // Check privacy settings
+        let allowlisted = ctx.accounts.allowlist_entry.is_some()
+            || proof.is_some_and(|proof| tip_jar.in_allowlist_tree(&sender.key(), &proof));
+        require!(
+            tip_jar.accepts_tips_from(&sender.key(), allowlisted),
+            TipJarError::NotAllowlisted
         );
-    
-        anchor_lang::solana_program::program::invoke(
-            &ix,
-            &[
-                sender.to_account_info(),
-                tip_jar.to_account_info(),
-            ],
+
+        // Move the tokens from the sender into the jar's vault
+        let balance_before = ctx.accounts.vault.amount;
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.memo_program.to_account_info(),
+            &ctx.accounts.sender_token_account.to_account_info(),
+            &ctx.accounts.mint,
+            &ctx.accounts.vault.to_account_info(),
+            &sender.to_account_info(),
+            &[],
+            amount,
+            &memo,
+            true,                          // Forward the sender's memo on-chain
         )?;
 
-        // Update the total_received in the TipJar
-        tip_jar.total_received += amount;
+        // Transfer-fee mints withhold part of the amount, so record what actually arrived
+        ctx.accounts.vault.reload()?;
+        let received = ctx.accounts.vault.amount - balance_before;
+
+        // Update the per-mint total and the tip history
+        tip_jar.record_token_amount(mint, received)?;
+        store_tip(
+            tip_jar,
+            ctx.accounts.tip_record.as_mut(),
+            ctx.bumps.tip_record,
+            sender.key(),
+            ctx.accounts.tip_history.as_ref(),
+            Tip {
+                sender: visibility.shown_sender(sender.key()),
+                amount: received,
+                mint: Some(mint),
+                visibility,
+                memo: memo.clone(),
+                timestamp: Clock::get()?.unix_timestamp as u64,
+            },
+        )?;
 
-        // Emit an event logging the tip info
         emit!(TipSent {
-            sender: sender.key(),
+            sender: visibility.shown_sender(sender.key()),
             receiver: tip_jar.key(),
-            amount,
+            amount: received,
+            fee: 0,
+            mint: Some(mint),
             memo,
             visibility,
         });
-    
-        // Check if goal has been reached
-        if tip_jar.total_received >= tip_jar.goal {
-            emit!(GoalReached {
-                tipjar: tip_jar.key(),
-                goal: tip_jar.goal,
-                total_received: tip_jar.total_received,
-            });
-        }
-        
+
         Ok(())
     }
 
-    /// Helper function to get tip history with pagination
-    pub fn get_tip_history(tip_jar: &TipJar, page: u32, page_size: u32) -> Vec<&Tip> {
-        let start = (page * page_size) as usize;
-        let end = std::cmp::min(start + page_size as usize, tip_jar.tips_history.len());
-        
-        if start >= tip_jar.tips_history.len() {
-            return vec![];
+    /// Returns SOL held in escrow for tips sent while a jar was inactive, plus the
+    /// sender's pledges if the jar's all-or-nothing campaign failed
+    /// Closes the escrow, so its rent goes back to the sender as well, unless it
+    /// still tracks a pledge to a campaign that hasn't failed
+    pub fn claim_refund(ctx: Context<ClaimRefund>) -> Result<()> {
+        let escrow = &mut ctx.accounts.refund_escrow;
+        let now = Clock::get()?.unix_timestamp;
+        let mut lamports = 0;
+
+        // Pledges are paid back out of the jar, and only once their campaign failed.
+        // A closed jar, or one whose campaign was claimed or replaced, already settled them.
+        let jar_info = ctx.accounts.tipjar.to_account_info();
+        let mut pledge_in_play = false;
+        if escrow.pledged > 0 && jar_info.owner == &crate::ID && !jar_info.data_is_empty() {
+            let mut tip_jar = TipJar::try_deserialize(&mut &jar_info.try_borrow_data()?[..])?;
+            if tip_jar.has_open_pledge(escrow) {
+                if tip_jar.campaign_failed(now) {
+                    let refund = tip_jar.unwind_pledge(escrow, ctx.accounts.contribution.as_deref_mut(), now)?;
+                    tip_jar.try_serialize(&mut &mut jar_info.try_borrow_mut_data()?[..])?;
+
+                    jar_info.sub_lamports(refund)?;
+                    ctx.accounts.sender.add_lamports(refund)?;
+                    lamports += refund;
+                } else {
+                    pledge_in_play = true;
+                }
+            }
         }
-        
-        // Return a slice of the tips history
-        tip_jar.tips_history[start..end].iter().collect()
-    }
 
-    /// Emits stats about a tip jar without fetching all tips
-    pub fn get_tip_stats(ctx: Context<GetTipStats>) -> Result<()> {
-        let tip_jar = &ctx.accounts.tipjar;
-        
-        emit!(TipJarStats {
-            tipjar: tip_jar.key(),
-            total_tips: tip_jar.total_tips_count,
-            total_received: tip_jar.total_received,
-            is_active: tip_jar.is_active,
-            goal_percentage: if tip_jar.goal > 0 {
-                (tip_jar.total_received * 100) / tip_jar.goal
-            } else {
-                0
-            },
+        if pledge_in_play {
+            // The escrow stays open to track the pledge, only the tips it holds are paid out
+            let amount = escrow.release_held_tips()?;
+            escrow.sub_lamports(amount)?;
+            ctx.accounts.sender.add_lamports(amount)?;
+            lamports += amount;
+        } else {
+            // Escrowed lamports and rent go back to the sender
+            lamports += escrow.amount;
+            escrow.close(ctx.accounts.sender.to_account_info())?;
+        }
+
+        emit!(TipRefunded {
+            tipjar: escrow.tipjar,
+            sender: escrow.sender,
+            lamports,
+            timestamp: now as u64,
         });
-        
+
         Ok(())
     }
 
-    /// Clears tip history while maintaining total count
-    pub fn clear_tip_history(ctx: Context<ClearTipHistory>) -> Result<()> {
+    /// Ends a failed campaign for one contributor by moving their pledge out of the
+    /// jar into their refund escrow, where they can still claim it with `claim_refund`.
+    /// Permissionless, so the owner can unlock the jar without waiting on every
+    /// contributor; once no pledges are left the jar leaves crowdfunding mode.
+    pub fn settle_failed_pledge(ctx: Context<SettleFailedPledge>) -> Result<()> {
         let tip_jar = &mut ctx.accounts.tipjar;
-        let owner = &ctx.accounts.owner;
-        
-        // Only owner can clear history
-        require_keys_eq!(tip_jar.owner, owner.key(), TipJarError::Unauthorized);
-        
-        // Clear tips history but maintain total count
-        tip_jar.tips_history.clear();
-        tip_jar.last_tip_index = 0;
-        
-        msg!("Tip history cleared while maintaining total count of {}", tip_jar.total_tips_count);
-        
+        let escrow = &mut ctx.accounts.refund_escrow;
+        let now = Clock::get()?.unix_timestamp;
+
+        let refund = tip_jar.settle_pledge(escrow, ctx.accounts.contribution.as_deref_mut(), now)?;
+        move_lamports(&tip_jar.to_account_info(), &escrow.to_account_info(), refund)?;
+
         Ok(())
     }
 
-    /// Toggles the active status of a tip jar
-    pub fn toggle_tipjar_status(ctx: Context<ToggleTipJarStatus>) -> Result<()> {
+    /// Makes the jar private, accepting tips only from the owner and allowlisted
+    /// senders, or public again
+    pub fn set_privacy(ctx: Context<SetPrivacy>, is_private: bool) -> Result<()> {
         let tip_jar = &mut ctx.accounts.tipjar;
-        let signer = &ctx.accounts.owner;
-    
-        // Security: Only the owner can pause/resume
-        require_keys_eq!(tip_jar.owner, signer.key(), TipJarError::Unauthorized);
-    
-        // Calculate the new status (opposite of current)
-        let new_status = !tip_jar.is_active;
-        
-        // Prevent redundant operations
-        require!(tip_jar.is_active != new_status, TipJarError::RedundantStatusChange);
-    
-        // Flip the active flag
-        tip_jar.is_active = new_status;
-    
-        emit!(TipJarStatusChanged {
+        require!(tip_jar.is_private != is_private, TipJarError::RedundantStatusChange);
+
+        tip_jar.is_private = is_private;
+
+        msg!("TipJar is now {}", if is_private { "private" } else { "public" });
+
+        Ok(())
+    }
+
+    /// Sets or clears the Merkle root of senders allowed to tip a private jar,
+    /// for allowlists too large to store as individual entries
+    pub fn set_allowlist_root(ctx: Context<SetPrivacy>, root: Option<[u8; 32]>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.allowlist_root = root;
+
+        emit!(AllowlistRootSet {
             tipjar: tip_jar.key(),
-            is_active: tip_jar.is_active,
+            root,
         });
+
         Ok(())
     }
 
-    /// Updates tip jar metadata (description, category, goal)
-    pub fn update_tipjar(ctx: Context<UpdateTipJar>, new_description: String, new_category: String, new_goal: u64) -> Result<()> {
-        let tip_jar = &mut ctx.accounts.tipjar;
-        let signer = &ctx.accounts.owner;
-    
-        // Only the owner can update the tip jar
-        require_keys_eq!(tip_jar.owner, signer.key(), TipJarError::Unauthorized);
-    
-        // Apply updates
-        tip_jar.description = new_description;
-        tip_jar.category = new_category;
-        tip_jar.goal = new_goal;
-    
-        msg!("TipJar updated successfully.");
-    
+    /// Allows `member` to tip the jar while it is private
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, member: Pubkey) -> Result<()> {
+        let entry = &mut ctx.accounts.allowlist_entry;
+        entry.tipjar = ctx.accounts.tipjar.key();
+        entry.member = member;
+        entry.bump = ctx.bumps.allowlist_entry;
+
+        emit!(AllowlistUpdated {
+            tipjar: entry.tipjar,
+            member,
+            allowed: true,
+        });
+
         Ok(())
     }
 
-    /// Allows the owner to withdraw funds from the tip jar
-    pub fn withdraw_tip(ctx: Context<WithdrawTip>, amount: u64) -> Result<()> {
+    /// Removes a member from the allowlist, returning the entry's rent to the owner
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> Result<()> {
+        let entry = &ctx.accounts.allowlist_entry;
+
+        emit!(AllowlistUpdated {
+            tipjar: entry.tipjar,
+            member: entry.member,
+            allowed: false,
+        });
+
+        Ok(())
+    }
+
+    /// Sets or clears the date by which the goal should be reached. With
+    /// `all_or_nothing`, SOL tips become pledges held by the jar until the
+    /// deadline: the owner claims them if the goal is met, otherwise every
+    /// contributor gets their pledges back through `claim_refund`.
+    pub fn set_goal_deadline(ctx: Context<SetGoalDeadline>, deadline: Option<i64>, all_or_nothing: bool) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        apply_goal_deadline(&mut ctx.accounts.tipjar, deadline, all_or_nothing, now)
+    }
+
+    /// Replaces the jar's milestone plan, given in order with cumulative lamport
+    /// targets. The last target becomes the jar's goal.
+    /// The plan is fixed once a milestone has been released.
+    pub fn set_milestones(ctx: Context<SetMilestones>, milestones: Vec<MilestoneTarget>) -> Result<()> {
         let tip_jar = &mut ctx.accounts.tipjar;
-        let signer = &ctx.accounts.owner;
+        tip_jar.set_milestones(milestones)?;
+
+        // Targets already covered by past tips count as reached
+        check_milestones(tip_jar);
+
+        msg!("Milestone plan updated with {} milestones", tip_jar.milestones.len());
+
+        Ok(())
+    }
+
+    /// Marks a reached milestone delivered, unlocking withdrawals up to its target
+    /// Milestones are released in order
+    pub fn release_milestone(ctx: Context<SetMilestones>, index: u8) -> Result<()> {
+        apply_milestone_release(&mut ctx.accounts.tipjar, index)
+    }
 
-        // Only the owner can withdraw
-        require_keys_eq!(tip_jar.owner, signer.key(), TipJarError::Unauthorized);
+    /// Ends a successful all-or-nothing campaign; the protocol fee on the pledges
+    /// goes to the treasury and the rest becomes withdrawable like any other tip
+    pub fn claim_crowdfunding(ctx: Context<ClaimCrowdfunding>) -> Result<()> {
+        apply_crowdfunding_claim(
+            &mut ctx.accounts.tipjar,
+            &ctx.accounts.config,
+            &ctx.accounts.treasury.to_account_info(),
+        )
+    }
 
-        // Ensure there are enough funds to withdraw
-        require!(tip_jar.total_received >= amount, TipJarError::InsufficientFunds);
+    /// Sets aside `amount` lamports for an anonymous tip, plus `relayer_fee` for whoever
+    /// delivers it. The voucher only records `commitment` (see `TipVoucher::commit`),
+    /// so nothing ties it to the receiving jar until it is relayed.
+    pub fn fund_voucher(ctx: Context<FundVoucher>, commitment: [u8; 32], amount: u64, relayer_fee: u64) -> Result<()> {
+        require!(amount > 0, TipJarError::InvalidAmount);
 
-        // Set a withdrawal limit for security
-        let withdraw_limit = 1000; // limit of 1000 SOL per withdrawal
-        require!(amount <= withdraw_limit, TipJarError::WithdrawalLimitExceeded);
+        let funder = &ctx.accounts.funder;
+        let voucher = &mut ctx.accounts.voucher;
+        let deposit = amount
+            .checked_add(relayer_fee)
+            .ok_or(TipJarError::InvalidAmount)?;
 
-        // Prepare the transfer instruction from tip jar to owner
         let ix = anchor_lang::solana_program::system_instruction::transfer(
-            &tip_jar.key(),
-            &signer.key(),
-            amount,
+            &funder.key(),
+            &voucher.key(),
+            deposit,
         );
-        
-        // Execute the transfer
         anchor_lang::solana_program::program::invoke(
             &ix,
             &[
-                tip_jar.to_account_info(),
-                signer.to_account_info(),
+                funder.to_account_info(),
+                voucher.to_account_info(),
             ],
         )?;
 
-        // Update the total_received in the TipJar
-        tip_jar.total_received -= amount;
-
-        msg!("Withdrawal successful. Amount withdrawn: {}", amount);
+        voucher.commitment = commitment;
+        voucher.amount = amount;
+        voucher.relayer_fee = relayer_fee;
+        voucher.bump = ctx.bumps.voucher;
 
         Ok(())
     }
 
-    /// Pauses a tip jar (sets is_active to false)
-    pub fn pause_tipjar(ctx: Context<PauseTipJar>) -> Result<()> {
-        let tip_jar = &mut ctx.accounts.tipjar;
-        let owner = &ctx.accounts.owner;
-    
-        // Ensure the caller is the owner of the tip jar
-        require_keys_eq!(tip_jar.owner, owner.key(), TipJarError::Unauthorized);
-    
-        // Set the TipJar to inactive (paused)
-        tip_jar.is_active = false;
-    
-        msg!("TipJar paused by the owner.");
-    
-        Ok(())
-    }
+    /// Delivers a voucher to the jar it was committed to as an anonymous tip.
+    /// The relayer reveals the secret and the opaque `refund_hash`, pays the
+    /// TipRecord rent and receives the relayer fee plus the voucher's rent.
+    pub fn relay_tip<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RelayTip<'info>>,
+        secret: [u8; 32],
+        memo: String,
+        refund_hash: [u8; 32],
+    ) -> Result<()> {
+        require!(memo.len() <= 100, TipJarError::MemoTooLong);
 
-    /// Resumes a paused tip jar (sets is_active to true)
-    pub fn resume_tipjar(ctx: Context<ResumeTipJar>) -> Result<()> {
         let tip_jar = &mut ctx.accounts.tipjar;
-        let owner = &ctx.accounts.owner;
-    
-        // Ensure the caller is the owner of the tip jar
-        require_keys_eq!(tip_jar.owner, owner.key(), TipJarError::Unauthorized);
-    
-        // Set the TipJar to active (resumed)
-        tip_jar.is_active = true;
-    
-        msg!("TipJar resumed by the owner.");
-    
-        Ok(())
-    }
+        let voucher = &ctx.accounts.voucher;
+        let tip_hash = TipVoucher::tip_hash(&secret, &tip_jar.key(), &memo);
+        require!(
+            TipVoucher::commit(&tip_hash, &refund_hash) == voucher.commitment,
+            TipJarError::InvalidVoucher
+        );
 
-    /// Closes a tip jar, transferring remaining funds to owner and recovering rent
-    pub fn close_tipjar(ctx: Context<CloseTipJar>) -> Result<()> {
-        let tip_jar = &mut ctx.accounts.tipjar;
-        let owner = &ctx.accounts.owner;
-    
-        // Ensure the caller is the owner of the tip jar
-        require_keys_eq!(tip_jar.owner, owner.key(), TipJarError::Unauthorized);
+        // Without a sender there is nobody to check against an allowlist or refund
+        require!(tip_jar.is_active, TipJarError::InactiveTipJar);
+        require!(!tip_jar.is_private && !tip_jar.crowdfunding, TipJarError::RelayNotAllowed);
+        tip_jar.check_tip_amount(voucher.amount)?;
 
-        // Get the remaining amount to transfer
-        let amount_to_transfer = tip_jar.total_received;
-    
-        if amount_to_transfer > 0 {
-            // Transfer any remaining SOL to the owner
-            let ix = anchor_lang::solana_program::system_instruction::transfer(
-                &tip_jar.key(),
-                &owner.key(),
-                amount_to_transfer,
-            );
-            anchor_lang::solana_program::program::invoke(
-                &ix,
-                &[
-                    tip_jar.to_account_info(),
-                    owner.to_account_info(),
-                ],
-            )?;
-        }
+        let voucher_info = voucher.to_account_info();
+        move_lamports(&voucher_info, &ctx.accounts.relayer.to_account_info(), voucher.relayer_fee)?;
 
-        // Close the TipJar account and recover rent
-        msg!("Closing TipJar and transferring {} SOL to owner", amount_to_transfer);
+        deliver_sol_tip(
+            tip_jar,
+            &voucher_info,
+            &ctx.accounts.config,
+            &ctx.accounts.treasury.to_account_info(),
+            ctx.accounts.tip_record.as_mut(),
+            ctx.bumps.tip_record,
+            ctx.accounts.relayer.key(),
+            ctx.accounts.tip_history.as_ref(),
+            ctx.remaining_accounts,
+            Tip {
+                sender: Pubkey::default(),
+                amount: voucher.amount,
+                mint: None,
+                visibility: Visibility::Anonymous,
+                memo,
+                timestamp: Clock::get()?.unix_timestamp as u64,
+            },
+        )?;
 
-        // Close account and send lamports to owner
-        let tip_jar_account_info = tip_jar.to_account_info();
-        let dest_account_info = owner.to_account_info();
-        **dest_account_info.lamports.borrow_mut() += **tip_jar_account_info.lamports.borrow();
-        **tip_jar_account_info.lamports.borrow_mut() = 0;
-        
         Ok(())
     }
+
+    /// Returns an unrelayed voucher, relayer fee and rent included, to the refund
+    /// wallet bound in its commitment. Anyone holding the secret can call it, e.g.
+    /// when the jar stopped accepting relayed tips; `tip_hash` stays opaque.
+    pub fn reclaim_voucher(ctx: Context<ReclaimVoucher>, secret: [u8; 32], tip_hash: [u8; 32]) -> Result<()> {
+        let voucher = &ctx.accounts.voucher;
+        let refund_hash = TipVoucher::refund_hash(&secret, &ctx.accounts.refund_to.key());
+        require!(
+            TipVoucher::commit(&tip_hash, &refund_hash) == voucher.commitment,
+            TipJarError::InvalidVoucher
+        );
+
+        emit!(VoucherReclaimed {
+            voucher: voucher.key(),
+            lamports: voucher.amount + voucher.relayer_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Starts a recurring tip of `amount` lamports every `interval` seconds for `periods`
+    /// payments. Every payment is prepaid into the subscription's escrow up front, and
+    /// the first one is due straight away.
+    /// Senders the jar wouldn't accept tips from can't lock funds in a subscription;
+    /// private jars take the sender's allowlist entry or a Merkle `proof`.
+    pub fn create_subscription(
+        ctx: Context<CreateSubscription>,
+        amount: u64,
+        interval: i64,
+        periods: u32,
+        visibility: Visibility,
+        memo: String,
+        proof: Option<Vec<[u8; 32]>>,
+    ) -> Result<()> {
+        require!(amount > 0 && periods > 0, TipJarError::InvalidAmount);
+        require!((1..=Subscription::MAX_INTERVAL).contains(&interval), TipJarError::InvalidSubscription);
+        require!(memo.len() <= Subscription::MAX_MEMO_LEN, TipJarError::MemoTooLong);
+        let tip_jar = &ctx.accounts.tipjar;
+        tip_jar.check_tip_amount(amount)?;
+
+        // The same gates as every payment, checked before the escrow is funded
+        let sender = &ctx.accounts.sender;
+        require!(ctx.accounts.blocked_sender.data_is_empty(), TipJarError::SenderBlocked);
+        let allowlisted = ctx.accounts.allowlist_entry.is_some()
+            || proof.is_some_and(|proof| tip_jar.in_allowlist_tree(&sender.key(), &proof));
+        require!(
+            tip_jar.accepts_tips_from(&sender.key(), allowlisted),
+            TipJarError::NotAllowlisted
+        );
+
+        let subscription = &mut ctx.accounts.subscription;
+        let prepaid = amount
+            .checked_mul(periods as u64)
+            .ok_or(TipJarError::InvalidAmount)?;
+
+        // Fund the escrow with every payment up front
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &sender.key(),
+            &subscription.key(),
+            prepaid,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                sender.to_account_info(),
+                subscription.to_account_info(),
+            ],
+        )?;
+
+        subscription.tipjar = tip_jar.key();
+        subscription.sender = sender.key();
+        subscription.amount = amount;
+        subscription.interval = interval;
+        subscription.next_due = Clock::get()?.unix_timestamp;
+        subscription.remaining_periods = periods;
+        subscription.visibility = visibility;
+        subscription.memo = memo;
+        subscription.bump = ctx.bumps.subscription;
+
+        emit!(SubscriptionCreated {
+            tipjar: subscription.tipjar,
+            sender: visibility.shown_sender(sender.key()),
+            amount,
+            interval,
+            periods,
+        });
+
+        Ok(())
+    }
+
+    /// Pays one due subscription period into the jar through the same path as `send_tip`
+    /// Permissionless; the caller pays the TipRecord rent. Once the last period is paid
+    /// the subscription closes and its rent goes back to the sender.
+    /// Subscribers to private jars are proven with their allowlist entry or a Merkle `proof`.
+    pub fn crank_subscription<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CrankSubscription<'info>>,
+        proof: Option<Vec<[u8; 32]>>,
+    ) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        let subscription = &mut ctx.accounts.subscription;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(subscription.remaining_periods > 0, TipJarError::SubscriptionComplete);
+        require!(now >= subscription.next_due, TipJarError::SubscriptionNotDue);
+        // Payments wait while the jar is paused or running an all-or-nothing
+        // campaign, since they can't be refunded as pledges; the sender can cancel meanwhile
+        require!(tip_jar.is_active, TipJarError::InactiveTipJar);
+        require!(!tip_jar.crowdfunding, TipJarError::PledgeRequired);
+        // The jar's tip bounds may have changed since the subscription was created
+        tip_jar.check_tip_amount(subscription.amount)?;
+        require!(ctx.accounts.blocked_sender.data_is_empty(), TipJarError::SenderBlocked);
+        let allowlisted = ctx.accounts.allowlist_entry.is_some()
+            || proof.is_some_and(|proof| tip_jar.in_allowlist_tree(&subscription.sender, &proof));
+        require!(
+            tip_jar.accepts_tips_from(&subscription.sender, allowlisted),
+            TipJarError::NotAllowlisted
+        );
+
+        let tip = Tip {
+            sender: subscription.visibility.shown_sender(subscription.sender),
+            amount: subscription.amount,
+            mint: None,
+            visibility: subscription.visibility,
+            memo: subscription.memo.clone(),
+            timestamp: now as u64,
+        };
+        let credited = deliver_sol_tip(
+            tip_jar,
+            &subscription.to_account_info(),
+            &ctx.accounts.config,
+            &ctx.accounts.treasury.to_account_info(),
+            ctx.accounts.tip_record.as_mut(),
+            ctx.bumps.tip_record,
+            ctx.accounts.cranker.key(),
+            ctx.accounts.tip_history.as_ref(),
+            ctx.remaining_accounts,
+            tip,
+        )?;
+        record_contribution(
+            ctx.accounts.contribution.as_mut(),
+            ctx.bumps.contribution,
+            tip_jar.key(),
+            subscription.sender,
+            credited,
+            subscription.visibility,
+            now,
+        )?;
+
+        // Missed periods stay due, so a late crank can catch up
+        subscription.next_due = subscription
+            .next_due
+            .checked_add(subscription.interval)
+            .ok_or(TipJarError::InvalidSubscription)?;
+        subscription.remaining_periods -= 1;
+
+        if subscription.remaining_periods == 0 {
+            subscription.close(ctx.accounts.sender.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    /// Stops a subscription and returns the unspent escrow and rent to the sender
+    pub fn cancel_subscription(ctx: Context<CancelSubscription>) -> Result<()> {
+        let subscription = &ctx.accounts.subscription;
+
+        emit!(SubscriptionCancelled {
+            tipjar: subscription.tipjar,
+            sender: subscription.visibility.shown_sender(subscription.sender),
+            refunded_periods: subscription.remaining_periods,
+            lamports: subscription.amount * subscription.remaining_periods as u64,
+        });
+
+        Ok(())
+    }
+
+    /// Reads a page of tip history in chronological order, starting at tip sequence `cursor`
+    /// (inclusive) or at the oldest/newest tip when `cursor` is `None`.
+    /// Inline-history jars read from the ring buffer; record jars read the TipRecord PDAs
+    /// for the requested sequences, passed as remaining accounts in page order.
+    /// The page is returned through `set_return_data`, so clients simulate this with `.view()`
+    pub fn get_tip_history<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetTipHistory<'info>>,
+        order: HistoryOrder,
+        cursor: Option<u32>,
+        limit: u8,
+    ) -> Result<TipHistoryPage> {
+        let tip_jar = &ctx.accounts.tipjar;
+        let limit = limit as usize;
+        require!(limit > 0 && limit <= MAX_HISTORY_PAGE_SIZE, TipJarError::HistoryPageTooLarge);
+
+        if tip_jar.history_mode == HistoryMode::Inline {
+            let tip_history = ctx
+                .accounts
+                .tip_history
+                .as_ref()
+                .ok_or(TipJarError::InvalidTipHistory)?;
+            return Ok(tip_history.load()?.page(order, cursor, limit));
+        }
+
+        read_tip_records(tip_jar, ctx.remaining_accounts, order, cursor, limit)
+    }
+
+    /// Emits stats about a tip jar without fetching all tips
+    pub fn get_tip_stats(ctx: Context<GetTipStats>) -> Result<()> {
+        let tip_jar = &ctx.accounts.tipjar;
+        
+        emit!(TipJarStats {
+            tipjar: tip_jar.key(),
+            total_tips: tip_jar.total_tips_count,
+            total_received: tip_jar.total_received,
+            is_active: tip_jar.is_active,
+            goal_percentage: tip_jar.total_received
+                .checked_mul(100)
+                .and_then(|pct| pct.checked_div(tip_jar.goal))
+                .unwrap_or(0),
+        });
+        
+        Ok(())
+    }
+
+    /// Clears tip history while maintaining total count
+    /// The TipRecord accounts to delete are passed as remaining accounts, each
+    /// followed by the wallet that paid its rent, which gets the rent back
+    pub fn clear_tip_history<'info>(ctx: Context<'_, '_, 'info, 'info, ClearTipHistory<'info>>) -> Result<()> {
+        let tip_jar = &ctx.accounts.tipjar;
+        require!(ctx.remaining_accounts.len().is_multiple_of(2), TipJarError::InvalidTipRecord);
+        
+        // Empty the inline ring buffer if the jar has one
+        if let Some(tip_history) = &ctx.accounts.tip_history {
+            tip_history.load_mut()?.clear();
+        }
+
+        // Close the given records but maintain total count. Anonymous records
+        // don't name their payer, so their rent goes to whoever clears them.
+        for accounts in ctx.remaining_accounts.chunks(2) {
+            let record = Account::<TipRecord>::try_from(&accounts[0])?;
+            require_keys_eq!(record.tipjar, tip_jar.key(), TipJarError::InvalidTipRecord);
+            let refund_to = if record.payer == Pubkey::default() {
+                ctx.accounts.authority.key()
+            } else {
+                record.payer
+            };
+            require_keys_eq!(accounts[1].key(), refund_to, TipJarError::InvalidTipRecord);
+            record.close(accounts[1].clone())?;
+        }
+        
+        msg!(
+            "Cleared {} tip records while maintaining total count of {}",
+            ctx.remaining_accounts.len() / 2,
+            tip_jar.total_tips_count
+        );
+        
+        Ok(())
+    }
+
+    /// Blocks `sender` from tipping the jar, callable by the owner or a moderator
+    pub fn block_sender(ctx: Context<BlockSender>, sender: Pubkey) -> Result<()> {
+        let entry = &mut ctx.accounts.blocked_sender;
+        entry.tipjar = ctx.accounts.tipjar.key();
+        entry.sender = sender;
+        entry.bump = ctx.bumps.blocked_sender;
+
+        emit!(BlocklistUpdated {
+            tipjar: entry.tipjar,
+            sender,
+            blocked: true,
+        });
+
+        Ok(())
+    }
+
+    /// Lets a blocked sender tip again, the entry's rent goes to the caller
+    pub fn unblock_sender(ctx: Context<UnblockSender>) -> Result<()> {
+        let entry = &ctx.accounts.blocked_sender;
+
+        emit!(BlocklistUpdated {
+            tipjar: entry.tipjar,
+            sender: entry.sender,
+            blocked: false,
+        });
+
+        Ok(())
+    }
+
+    /// Blanks the memo of the tip with sequence `sequence`, keeping its amount in the totals
+    /// Pass the tip's record, or the ring buffer for inline-history jars
+    pub fn redact_tip(ctx: Context<RedactTip>, sequence: u32) -> Result<()> {
+        if let Some(tip_record) = ctx.accounts.tip_record.as_mut() {
+            tip_record.tip.memo.clear();
+        } else {
+            let tip_history = ctx
+                .accounts
+                .tip_history
+                .as_ref()
+                .ok_or(TipJarError::InvalidTipRecord)?;
+            require!(tip_history.load_mut()?.redact(sequence), TipJarError::InvalidTipRecord);
+        }
+
+        emit!(TipRedacted {
+            tipjar: ctx.accounts.tipjar.key(),
+            sequence,
+            redacted_by: ctx.accounts.authority.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Chooses whether tips to an inactive jar are rejected or held for refund
+    pub fn set_inactive_policy(ctx: Context<SetInactivePolicy>, policy: InactiveTipPolicy) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.inactive_policy = policy;
+
+        msg!("Inactive tip policy updated.");
+
+        Ok(())
+    }
+
+    /// Switches the jar to an inline zero-copy ring buffer for tip history
+    /// Cheaper per tip than a TipRecord, but only the latest tips are kept
+    pub fn enable_inline_history(ctx: Context<EnableInlineHistory>) -> Result<()> {
+        start_inline_history(&mut ctx.accounts.tipjar, &ctx.accounts.tip_history)
+    }
+
+    /// Switches the jar back to per-tip records and closes the ring buffer
+    pub fn disable_inline_history(ctx: Context<DisableInlineHistory>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.history_mode = HistoryMode::Records;
+
+        msg!("Inline tip history disabled, new tips will be stored as records");
+
+        Ok(())
+    }
+
+    /// Toggles the active status of a tip jar
+    pub fn toggle_tipjar_status(ctx: Context<ToggleTipJarStatus>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+    
+        // Calculate the new status (opposite of current)
+        let new_status = !tip_jar.is_active;
+        
+        // Prevent redundant operations
+        require!(tip_jar.is_active != new_status, TipJarError::RedundantStatusChange);
+    
+        // Flip the active flag
+        tip_jar.is_active = new_status;
+    
+        emit!(TipJarStatusChanged {
+            tipjar: tip_jar.key(),
+            is_active: tip_jar.is_active,
+        });
+        Ok(())
+    }
+
+    /// Updates tip jar metadata (description, category, goal)
+    pub fn update_tipjar(ctx: Context<UpdateTipJar>, new_description: String, new_category: String, new_goal: u64) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+    
+        // Apply updates
+        tip_jar.update_metadata(new_description, new_category, new_goal)?;
+    
+        msg!("TipJar updated successfully.");
+    
+        Ok(())
+    }
+
+    /// Sets the smallest and largest SOL tip the jar accepts, and the amount below
+    /// which tips are counted but not kept in the history
+    pub fn set_tip_limits(ctx: Context<UpdateTipJar>, min_tip: u64, max_tip: Option<u64>, history_threshold: u64) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.set_tip_limits(min_tip, max_tip, history_threshold)?;
+
+        msg!("TipJar tip limits updated.");
+
+        Ok(())
+    }
+
+    /// Allows the owner, or a treasurer within its cap, to withdraw funds from the tip jar
+    /// Only the balance above the jar's rent-exempt minimum can be withdrawn
+    pub fn withdraw_tip(ctx: Context<WithdrawTip>, amount: u64) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        require!(amount > 0, TipJarError::InvalidAmount);
+
+        // Treasurers draw down their own cap on top of the jar's limits
+        if ctx.accounts.authority.key() != tip_jar.owner {
+            if let Some(delegate) = &mut ctx.accounts.delegate {
+                delegate.record_withdrawal(amount)?;
+            }
+        }
+
+        // Timelocked jars must go through request_withdrawal
+        let now = Clock::get()?.unix_timestamp;
+        tip_jar.require_no_timelock(now)?;
+
+        // Enforce the owner's per-withdrawal and per-window limits
+        tip_jar.record_withdrawal(amount, now)?;
+
+        // Move the lamports out of the jar, failing if they would dip into rent
+        pay_out_lamports(tip_jar, &ctx.accounts.destination.to_account_info(), amount)?;
+
+        msg!("Withdrawal successful. Amount withdrawn: {}", amount);
+
+        Ok(())
+    }
+
+    /// Starts a timelocked withdrawal that can execute after the jar's timelock
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, amount: u64, destination: Pubkey) -> Result<()> {
+        require!(amount > 0, TipJarError::InvalidAmount);
+
+        let tip_jar = &mut ctx.accounts.tipjar;
+
+        // A treasurer's cap is spent when the request is made, and given back if it is cancelled
+        let authority = ctx.accounts.authority.key();
+        let mut charged_cap = false;
+        if authority != tip_jar.owner {
+            if let Some(delegate) = &mut ctx.accounts.delegate {
+                delegate.record_withdrawal(amount)?;
+                charged_cap = true;
+            }
+        }
+        let now = Clock::get()?.unix_timestamp;
+
+        let pending = &mut ctx.accounts.pending_withdrawal;
+        pending.tipjar = tip_jar.key();
+        pending.id = tip_jar.next_withdrawal_id;
+        pending.amount = amount;
+        pending.destination = destination;
+        pending.requester = authority;
+        pending.charged_cap = charged_cap;
+        pending.requested_at = now;
+        pending.unlock_at = tip_jar.withdrawal_unlock_at(now);
+        pending.bump = ctx.bumps.pending_withdrawal;
+
+        tip_jar.next_withdrawal_id += 1;
+
+        emit!(WithdrawalRequested {
+            tipjar: tip_jar.key(),
+            id: pending.id,
+            amount,
+            destination,
+            unlock_at: pending.unlock_at,
+        });
+
+        Ok(())
+    }
+
+    /// Pays out a requested withdrawal once its timelock has passed
+    /// Permissionless: the destination was fixed when the request was made
+    pub fn execute_withdrawal(ctx: Context<ExecuteWithdrawal>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        let pending = &ctx.accounts.pending_withdrawal;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(pending.is_unlocked(now), TipJarError::WithdrawalStillLocked);
+
+        // Limits are checked when the lamports actually move
+        tip_jar.record_withdrawal(pending.amount, now)?;
+        pay_out_lamports(tip_jar, &ctx.accounts.destination.to_account_info(), pending.amount)?;
+
+        msg!("Timelocked withdrawal {} executed", pending.id);
+
+        Ok(())
+    }
+
+    /// Aborts a requested withdrawal before it executes, giving a treasurer back
+    /// the cap it spent and the requester back the request's rent
+    pub fn cancel_withdrawal(ctx: Context<CancelWithdrawal>) -> Result<()> {
+        let pending = &ctx.accounts.pending_withdrawal;
+        restore_treasurer_cap(pending, &ctx.accounts.delegate)?;
+
+        emit!(WithdrawalCancelled {
+            tipjar: pending.tipjar,
+            id: pending.id,
+            amount: pending.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Updates the SOL withdrawal limits and timelock
+    /// Tighter settings apply immediately; anything looser is queued and only
+    /// takes effect after `WithdrawalPolicy::LOOSENING_DELAY`
+    pub fn set_withdrawal_policy(ctx: Context<SetWithdrawalPolicy>, policy: WithdrawalPolicy) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        apply_withdrawal_policy(&mut ctx.accounts.tipjar, policy, now)
+    }
+
+    /// Allows the owner to withdraw SPL tokens from one of the jar's vaults
+    /// Disabled while the jar has a timelock, which token payouts can't wait out
+    pub fn withdraw_token_tip(ctx: Context<WithdrawTokenTip>, amount: u64) -> Result<()> {
+        ctx.accounts.tipjar.require_no_timelock(Clock::get()?.unix_timestamp)?;
+
+        pay_out_tokens(
+            &ctx.accounts.tipjar,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.memo_program.to_account_info(),
+            &ctx.accounts.vault,
+            &ctx.accounts.mint,
+            &ctx.accounts.destination.to_account_info(),
+            amount,
+        )?;
+
+        msg!("Token withdrawal successful. Amount withdrawn: {}", amount);
+
+        Ok(())
+    }
+
+    /// Stops tracking a token mint, e.g. junk tipped to fill the jar's mint slots or
+    /// a vault frozen by its mint. The vault is left out of the close sweep; any
+    /// tokens still in it can be withdrawn with `withdraw_token_tip` beforehand.
+    pub fn forget_token_mint(ctx: Context<ForgetTokenMint>, mint: Pubkey) -> Result<()> {
+        apply_forget_token_mint(&mut ctx.accounts.tipjar, mint)
+    }
+
+    /// Pauses a tip jar (sets is_active to false)
+    pub fn pause_tipjar(ctx: Context<PauseTipJar>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+    
+        // Set the TipJar to inactive (paused)
+        tip_jar.is_active = false;
+    
+        msg!("TipJar paused by {}.", ctx.accounts.authority.key());
+    
+        Ok(())
+    }
+
+    /// Resumes a paused tip jar (sets is_active to true)
+    pub fn resume_tipjar(ctx: Context<ResumeTipJar>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+    
+        // Set the TipJar to active (resumed)
+        tip_jar.is_active = true;
+    
+        msg!("TipJar resumed by {}.", ctx.accounts.authority.key());
+    
+        Ok(())
+    }
+
+    /// Closes a tip jar, transferring remaining funds to owner and recovering rent
+    /// Every token vault must be passed as a (vault, mint, owner token account)
+    /// triple in the remaining accounts so its balance is swept back to the owner.
+    /// Vaults that can't be swept can be dropped first with `forget_token_mint`.
+    pub fn close_tipjar<'info>(ctx: Context<'_, '_, 'info, 'info, CloseTipJar<'info>>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        let owner = &ctx.accounts.owner;
+    
+        // Ensure the caller is the owner of the tip jar
+        require_keys_eq!(tip_jar.owner, owner.key(), TipJarError::Unauthorized);
+
+        // Pledges must be claimed or refunded first, and unreleased milestone funds stay put
+        require!(tip_jar.locked_lamports() == 0, TipJarError::CrowdfundingLocked);
+        require!(
+            available_balance(tip_jar)? <= tip_jar.release_headroom(),
+            TipJarError::MilestoneNotReleased
+        );
+
+        // Closing pays out the balance and sweeps the vaults, so it is held to the
+        // withdrawal policy. Timelocked jars, or balances over the limits, must be
+        // withdrawn first.
+        let now = Clock::get()?.unix_timestamp;
+        let balance = available_balance(tip_jar)?;
+        if tip_jar.close_pays_out(balance) {
+            tip_jar.require_no_timelock(now)?;
+        }
+        if balance > 0 {
+            tip_jar.record_withdrawal(balance, now)?;
+        }
+
+        // Drop the jar from the owner's registry
+        let jar_key = tip_jar.key();
+        ctx.accounts.registry.jars.retain(|jar| *jar != jar_key);
+
+        // Sweep and close every token vault before the jar goes away
+        sweep_token_vaults(
+            tip_jar,
+            &owner.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_2022_program.to_account_info(),
+            &ctx.accounts.memo_program.to_account_info(),
+            ctx.remaining_accounts,
+        )?;
+
+        // Get the remaining amount to transfer
+        let amount_to_transfer = available_balance(tip_jar)?;
+
+        // The `close = owner` constraint moves the balance and the rent to the owner
+        msg!("Closing TipJar and transferring {} lamports plus rent to owner", amount_to_transfer);
+        
+        Ok(())
+    }
+
+    /// Grants or updates scoped roles for another key on this jar
+    /// `roles` is a bitmask of `Delegate::MODERATOR`, `Delegate::EDITOR` and
+    /// `Delegate::TREASURER`; `withdraw_cap` only matters for treasurers
+    pub fn set_delegate(ctx: Context<SetDelegate>, delegate_key: Pubkey, roles: u8, withdraw_cap: u64) -> Result<()> {
+        grant_delegate(
+            &ctx.accounts.tipjar,
+            &mut ctx.accounts.delegate,
+            delegate_key,
+            roles,
+            withdraw_cap,
+            ctx.bumps.delegate,
+        )
+    }
+
+    /// Removes every role from a delegate and returns its rent to the owner
+    pub fn revoke_delegate(ctx: Context<RevokeDelegate>) -> Result<()> {
+        let delegate = &ctx.accounts.delegate;
+
+        emit!(DelegateUpdated {
+            tipjar: delegate.tipjar,
+            delegate: delegate.delegate,
+            roles: 0,
+            withdraw_cap: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Starts handing the jar to `new_owner`, who must accept before anything changes
+    /// Proposing again replaces the pending owner
+    pub fn propose_owner_transfer(ctx: Context<ProposeOwnerTransfer>, new_owner: Pubkey) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        require_keys_neq!(new_owner, tip_jar.owner, TipJarError::InvalidOwnerTransfer);
+
+        tip_jar.pending_owner = Some(new_owner);
+
+        msg!("Ownership transfer to {} proposed", new_owner);
+
+        Ok(())
+    }
+
+    /// Completes a transfer; the jar keeps its address and moves to the new owner's registry
+    /// Delegates granted by the previous owner stop working
+    pub fn accept_owner_transfer(ctx: Context<AcceptOwnerTransfer>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        let new_owner = &ctx.accounts.new_owner;
+        let jar_key = tip_jar.key();
+
+        // Move the jar between the two owners' registries
+        ctx.accounts.previous_registry.jars.retain(|jar| *jar != jar_key);
+
+        let new_registry = &mut ctx.accounts.new_registry;
+        if new_registry.owner == Pubkey::default() {
+            new_registry.owner = new_owner.key();
+            new_registry.bump = ctx.bumps.new_registry;
+        }
+        require!(new_registry.jars.len() < OwnerRegistry::MAX_JARS, TipJarError::TooManyTipJars);
+        new_registry.jars.push(jar_key);
+
+        let previous_owner = tip_jar.owner;
+        tip_jar.owner = new_owner.key();
+        tip_jar.pending_owner = None;
+
+        emit!(OwnershipTransferred {
+            tipjar: jar_key,
+            previous_owner,
+            new_owner: new_owner.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws a pending ownership transfer
+    pub fn cancel_owner_transfer(ctx: Context<CancelOwnerTransfer>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        require!(tip_jar.pending_owner.is_some(), TipJarError::NoPendingOwnerTransfer);
+
+        tip_jar.pending_owner = None;
+
+        msg!("Ownership transfer cancelled");
+
+        Ok(())
+    }
+
+    /// Replaces the jar's split table; shares are in basis points and must add up to 10,000
+    /// Pass an empty table to remove the splits
+    pub fn set_splits(ctx: Context<SetSplits>, splits: Vec<SplitShare>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.set_splits(splits)?;
+
+        msg!("Split table updated with {} recipients", tip_jar.splits.len());
+
+        Ok(())
+    }
+
+    /// Chooses whether SOL tips are paid straight to the split table as they arrive
+    /// instead of collecting in the jar for a later `distribute`
+    pub fn set_split_on_receive(ctx: Context<SetSplits>, enabled: bool) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.set_split_on_receive(enabled)?;
+
+        msg!("Split on receive {}", if enabled { "enabled" } else { "disabled" });
+
+        Ok(())
+    }
+
+    /// Pays the available balance out to the split table in one transaction
+    /// Permissionless, since the recipients are fixed by the owner. Recipients are
+    /// passed as writable remaining accounts in split table order. Rounding dust goes
+    /// to the first recipient, and the payout is capped by the withdrawal policy.
+    pub fn distribute<'info>(ctx: Context<'_, '_, 'info, 'info, Distribute<'info>>) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        require!(!tip_jar.splits.is_empty(), TipJarError::NoSplits);
+        require!(ctx.remaining_accounts.len() == tip_jar.splits.len(), TipJarError::InvalidSplitRecipient);
+
+        // Timelocked jars only pay out through request_withdrawal
+        let now = Clock::get()?.unix_timestamp;
+        tip_jar.require_no_timelock(now)?;
+
+        let amount = available_balance(tip_jar)?
+            .min(tip_jar.withdrawal_allowance(now))
+            .min(tip_jar.release_headroom());
+        require!(amount > 0, TipJarError::InsufficientFunds);
+        tip_jar.record_withdrawal(amount, now)?;
+
+        let shares = tip_jar.split_shares(amount);
+        let splits = tip_jar.splits.clone();
+        for ((split, share), recipient) in splits.iter().zip(shares).zip(ctx.remaining_accounts.iter()) {
+            require_keys_eq!(recipient.key(), split.recipient, TipJarError::InvalidSplitRecipient);
+            if share == 0 {
+                continue;
+            }
+
+            pay_out_lamports(tip_jar, recipient, share)?;
+
+            emit!(Distributed {
+                tipjar: tip_jar.key(),
+                recipient: split.recipient,
+                bps: split.bps,
+                amount: share,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Hands control of withdrawals, updates and closure to an M-of-N owner set
+    /// Once set, further owner set changes must go through a proposal
+    pub fn set_owner_set(ctx: Context<SetOwnerSet>, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        tip_jar.set_owner_set(signers, threshold)?;
+
+        // A multisig jar only changes hands through its members
+        tip_jar.pending_owner = None;
+
+        emit!(OwnerSetChanged {
+            tipjar: tip_jar.key(),
+            signers: tip_jar.signers.clone(),
+            threshold: tip_jar.threshold,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a proposal for a multisig jar; the proposer's approval is counted
+    pub fn create_proposal(ctx: Context<CreateProposal>, action: ProposalAction) -> Result<()> {
+        let tip_jar = &mut ctx.accounts.tipjar;
+        let proposer = &ctx.accounts.proposer;
+
+        // Validate the parts of the action that don't depend on accounts
+        match &action {
+            ProposalAction::Withdraw { amount, .. } | ProposalAction::WithdrawToken { amount, .. } => {
+                require!(*amount > 0, TipJarError::InvalidAmount);
+            }
+            ProposalAction::Update { description, category, goal } => {
+                require!(*goal > 0, TipJarError::InvalidGoal);
+                require!(description.len() <= TipJar::MAX_DESCRIPTION_LEN, TipJarError::DescriptionTooLong);
+                require!(category.len() <= TipJar::MAX_CATEGORY_LEN, TipJarError::CategoryTooLong);
+            }
+            ProposalAction::SetOwnerSet { signers, .. } => {
+                require!(signers.len() <= TipJar::MAX_SIGNERS, TipJarError::InvalidOwnerSet);
+            }
+            ProposalAction::SetSplits { splits, split_on_receive } => {
+                require!(splits.len() <= TipJar::MAX_SPLITS, TipJarError::InvalidSplits);
+                require!(!*split_on_receive || !splits.is_empty(), TipJarError::NoSplits);
+            }
+            ProposalAction::SetMilestones { milestones } => {
+                require!(milestones.len() <= TipJar::MAX_MILESTONES, TipJarError::InvalidMilestones);
+            }
+            ProposalAction::SetWithdrawalPolicy { policy } => {
+                require!(policy.is_valid(), TipJarError::InvalidWithdrawalPolicy);
+            }
+            ProposalAction::SetDelegate { delegate, roles, .. } => {
+                require!(
+                    *roles != 0 && *roles & !Delegate::ALL_ROLES == 0,
+                    TipJarError::InvalidDelegateRoles
+                );
+                require_keys_neq!(*delegate, tip_jar.owner, TipJarError::InvalidDelegateRoles);
+            }
+            ProposalAction::SetTipLimits { min_tip, max_tip, .. } => {
+                if let Some(max) = max_tip {
+                    require!(*max > 0 && *max >= *min_tip, TipJarError::InvalidTipLimits);
+                }
+            }
+            // The deadline is checked against the clock when the proposal executes
+            ProposalAction::SetGoalDeadline { .. }
+            | ProposalAction::ReleaseMilestone { .. }
+            | ProposalAction::Close { .. }
+            | ProposalAction::RevokeDelegate { .. }
+            | ProposalAction::ClaimCrowdfunding
+            | ProposalAction::CancelWithdrawal { .. }
+            | ProposalAction::SetInlineHistory { .. }
+            | ProposalAction::SetPrivacy { .. }
+            | ProposalAction::AddToAllowlist { .. }
+            | ProposalAction::RemoveFromAllowlist { .. }
+            | ProposalAction::SetAllowlistRoot { .. }
+            | ProposalAction::ForgetTokenMint { .. } => {}
+        }
+
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.tipjar = tip_jar.key();
+        proposal.id = tip_jar.next_proposal_id;
+        proposal.proposer = proposer.key();
+        proposal.action = action;
+        proposal.approvals = vec![proposer.key()];
+        proposal.created_at = Clock::get()?.unix_timestamp;
+        proposal.bump = ctx.bumps.proposal;
+
+        tip_jar.next_proposal_id += 1;
+
+        emit!(ProposalCreated {
+            tipjar: tip_jar.key(),
+            id: proposal.id,
+            proposer: proposer.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Adds a member's approval to a proposal
+    pub fn approve_proposal(ctx: Context<ApproveProposal>) -> Result<()> {
+        let tip_jar = &ctx.accounts.tipjar;
+        let proposal = &mut ctx.accounts.proposal;
+        let approver = ctx.accounts.approver.key();
+
+        proposal.approve(tip_jar, approver)?;
+
+        emit!(ProposalApproved {
+            tipjar: tip_jar.key(),
+            id: proposal.id,
+            approver,
+            approvals: proposal.approval_count(tip_jar) as u8,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws a proposal before it executes, returning its rent to the proposer
+    pub fn cancel_proposal(ctx: Context<CancelProposal>) -> Result<()> {
+        msg!("Proposal {} cancelled", ctx.accounts.proposal.id);
+
+        Ok(())
+    }
+
+    /// Executes a proposal once enough current members have approved it
+    /// Closing proposals pass each token vault as a (vault, mint, destination)
+    /// triple in the remaining accounts, as in `close_tipjar`
+    pub fn execute_proposal<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteProposal<'info>>) -> Result<()> {
+        let accounts = &mut *ctx.accounts;
+        let tip_jar = &mut accounts.tipjar;
+        let proposal = &accounts.proposal;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(
+            proposal.approval_count(tip_jar) >= tip_jar.threshold as usize,
+            TipJarError::NotEnoughApprovals
+        );
+
+        match proposal.action.clone() {
+            ProposalAction::Withdraw { amount, destination } => {
+                let destination_info = accounts
+                    .destination
+                    .as_ref()
+                    .ok_or(TipJarError::InvalidProposalAccounts)?;
+                require_keys_eq!(destination_info.key(), destination, TipJarError::InvalidProposalAccounts);
+
+                // The jar's timelock runs from when the proposal was created
+                tip_jar.require_timelock_passed(proposal.created_at, now)?;
+                tip_jar.record_withdrawal(amount, now)?;
+                pay_out_lamports(tip_jar, &destination_info.to_account_info(), amount)?;
+            }
+            ProposalAction::WithdrawToken { mint, amount, destination } => {
+                let (Some(mint_account), Some(vault), Some(token_destination), Some(token_program), Some(memo_program)) = (
+                    &accounts.mint,
+                    &accounts.vault,
+                    &accounts.token_destination,
+                    &accounts.token_program,
+                    &accounts.memo_program,
+                ) else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                require_keys_eq!(mint_account.key(), mint, TipJarError::InvalidProposalAccounts);
+                require_keys_eq!(token_destination.key(), destination, TipJarError::InvalidProposalAccounts);
+                require_keys_eq!(
+                    vault.key(),
+                    get_associated_token_address_with_program_id(&tip_jar.key(), &mint, &token_program.key()),
+                    TipJarError::MissingTokenVault
+                );
+                tip_jar.require_timelock_passed(proposal.created_at, now)?;
+
+                pay_out_tokens(
+                    tip_jar,
+                    &token_program.to_account_info(),
+                    &memo_program.to_account_info(),
+                    vault,
+                    mint_account,
+                    &token_destination.to_account_info(),
+                    amount,
+                )?;
+            }
+            ProposalAction::Update { description, category, goal } => {
+                tip_jar.update_metadata(description, category, goal)?;
+            }
+            ProposalAction::SetOwnerSet { signers, threshold } => {
+                tip_jar.set_owner_set(signers, threshold)?;
+
+                emit!(OwnerSetChanged {
+                    tipjar: tip_jar.key(),
+                    signers: tip_jar.signers.clone(),
+                    threshold: tip_jar.threshold,
+                });
+            }
+            ProposalAction::SetSplits { splits, split_on_receive } => {
+                tip_jar.set_splits(splits)?;
+                tip_jar.set_split_on_receive(split_on_receive)?;
+            }
+            ProposalAction::SetMilestones { milestones } => {
+                tip_jar.set_milestones(milestones)?;
+                check_milestones(tip_jar);
+            }
+            ProposalAction::ReleaseMilestone { index } => {
+                apply_milestone_release(tip_jar, index)?;
+            }
+            ProposalAction::SetWithdrawalPolicy { policy } => {
+                apply_withdrawal_policy(tip_jar, policy, now)?;
+            }
+            ProposalAction::SetGoalDeadline { deadline, all_or_nothing } => {
+                apply_goal_deadline(tip_jar, deadline, all_or_nothing, now)?;
+            }
+            ProposalAction::SetDelegate { delegate, roles, withdraw_cap } => {
+                let Some(delegate_account) = accounts.delegate.as_mut() else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                let bump = ctx.bumps.delegate.ok_or(TipJarError::InvalidProposalAccounts)?;
+                grant_delegate(tip_jar, delegate_account, delegate, roles, withdraw_cap, bump)?;
+            }
+            ProposalAction::SetTipLimits { min_tip, max_tip, history_threshold } => {
+                tip_jar.set_tip_limits(min_tip, max_tip, history_threshold)?;
+            }
+            ProposalAction::RevokeDelegate { delegate } => {
+                let Some(delegate_account) = accounts.delegate.as_mut() else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                // An account created on the spot holds no grant to revoke
+                require_keys_eq!(delegate_account.tipjar, tip_jar.key(), TipJarError::InvalidProposalAccounts);
+
+                emit!(DelegateUpdated {
+                    tipjar: tip_jar.key(),
+                    delegate,
+                    roles: 0,
+                    withdraw_cap: 0,
+                });
+                delegate_account.close(accounts.proposer.to_account_info())?;
+            }
+            ProposalAction::ClaimCrowdfunding => {
+                let (Some(config), Some(treasury)) = (&accounts.config, &accounts.treasury) else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                require_keys_eq!(treasury.key(), config.treasury, TipJarError::InvalidProposalAccounts);
+                apply_crowdfunding_claim(tip_jar, config, &treasury.to_account_info())?;
+            }
+            ProposalAction::CancelWithdrawal { id } => {
+                let (Some(pending), Some(requester)) = (accounts.pending_withdrawal.as_mut(), &accounts.destination) else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                require!(pending.id == id, TipJarError::InvalidProposalAccounts);
+                require_keys_eq!(requester.key(), pending.requester, TipJarError::InvalidProposalAccounts);
+
+                // A treasurer's request passes its delegate account first in the remaining accounts
+                if pending.charged_cap {
+                    let delegate_info = ctx.remaining_accounts.first().ok_or(TipJarError::InvalidProposalAccounts)?;
+                    restore_treasurer_cap(pending, delegate_info)?;
+                }
+
+                emit!(WithdrawalCancelled {
+                    tipjar: pending.tipjar,
+                    id: pending.id,
+                    amount: pending.amount,
+                });
+                pending.close(requester.to_account_info())?;
+            }
+            ProposalAction::SetInlineHistory { enabled } => {
+                let Some(tip_history) = &accounts.tip_history else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                if enabled {
+                    start_inline_history(tip_jar, tip_history)?;
+                } else {
+                    require!(tip_jar.history_mode == HistoryMode::Inline, TipJarError::RedundantStatusChange);
+                    tip_jar.history_mode = HistoryMode::Records;
+                    tip_history.close(accounts.proposer.to_account_info())?;
+
+                    msg!("Inline tip history disabled, new tips will be stored as records");
+                }
+            }
+            ProposalAction::SetPrivacy { is_private } => {
+                require!(tip_jar.is_private != is_private, TipJarError::RedundantStatusChange);
+                tip_jar.is_private = is_private;
+
+                msg!("TipJar is now {}", if is_private { "private" } else { "public" });
+            }
+            ProposalAction::AddToAllowlist { member } => {
+                let (Some(entry), Some(bump)) = (accounts.allowlist_entry.as_mut(), ctx.bumps.allowlist_entry) else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                entry.tipjar = tip_jar.key();
+                entry.member = member;
+                entry.bump = bump;
+
+                emit!(AllowlistUpdated {
+                    tipjar: entry.tipjar,
+                    member,
+                    allowed: true,
+                });
+            }
+            ProposalAction::RemoveFromAllowlist { member } => {
+                let Some(entry) = accounts.allowlist_entry.as_mut() else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                // An account created on the spot holds no entry to remove
+                require_keys_eq!(entry.tipjar, tip_jar.key(), TipJarError::InvalidProposalAccounts);
+
+                emit!(AllowlistUpdated {
+                    tipjar: entry.tipjar,
+                    member,
+                    allowed: false,
+                });
+                entry.close(accounts.proposer.to_account_info())?;
+            }
+            ProposalAction::SetAllowlistRoot { root } => {
+                tip_jar.allowlist_root = root;
+
+                emit!(AllowlistRootSet {
+                    tipjar: tip_jar.key(),
+                    root,
+                });
+            }
+            ProposalAction::ForgetTokenMint { mint } => {
+                apply_forget_token_mint(tip_jar, mint)?;
+            }
+            ProposalAction::Close { destination } => {
+                let (Some(destination_info), Some(registry), Some(token_program), Some(token_2022_program), Some(memo_program)) = (
+                    &accounts.destination,
+                    accounts.registry.as_mut(),
+                    &accounts.token_program,
+                    &accounts.token_2022_program,
+                    &accounts.memo_program,
+                ) else {
+                    return err!(TipJarError::InvalidProposalAccounts);
+                };
+                require_keys_eq!(destination_info.key(), destination, TipJarError::InvalidProposalAccounts);
+                require_keys_eq!(token_2022_program.key(), spl_token_2022::ID, TipJarError::InvalidProposalAccounts);
+                require!(tip_jar.locked_lamports() == 0, TipJarError::CrowdfundingLocked);
+                require!(
+                    available_balance(tip_jar)? <= tip_jar.release_headroom(),
+                    TipJarError::MilestoneNotReleased
+                );
+
+                // The payout counts as a withdrawal, timelocked from when the proposal was created
+                let balance = available_balance(tip_jar)?;
+                if tip_jar.close_pays_out(balance) {
+                    tip_jar.require_timelock_passed(proposal.created_at, now)?;
+                }
+                if balance > 0 {
+                    tip_jar.record_withdrawal(balance, now)?;
+                }
+
+                sweep_token_vaults(
+                    tip_jar,
+                    &destination_info.to_account_info(),
+                    &token_program.to_account_info(),
+                    &token_2022_program.to_account_info(),
+                    &memo_program.to_account_info(),
+                    ctx.remaining_accounts,
+                )?;
+
+                // Drop the jar from the owner's registry and hand everything to the destination
+                let jar_key = tip_jar.key();
+                registry.jars.retain(|jar| *jar != jar_key);
+                tip_jar.close(destination_info.to_account_info())?;
+            }
+        }
+
+        emit!(ProposalExecuted {
+            tipjar: proposal.tipjar,
+            id: proposal.id,
+        });
+
+        Ok(())
+    }
+}
+
+/// Lamports held by the jar above its rent-exempt minimum and any locked pledges
+fn available_balance(tip_jar: &Account<TipJar>) -> Result<u64> {
+    let info = tip_jar.to_account_info();
+    let rent_exempt = Rent::get()?.minimum_balance(info.data_len());
+    Ok(info
+        .lamports()
+        .saturating_sub(rent_exempt)
+        .saturating_sub(tip_jar.locked_lamports()))
+}
+
+/// Pays `amount` lamports out of the jar to `destination` and emits `Withdrawn`.
+/// The jar is program-owned, so it is debited directly rather than through the
+/// System Program, and it can never drop below its rent-exempt minimum.
+fn pay_out_lamports<'info>(
+    tip_jar: &mut Account<'info, TipJar>,
+    destination: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let available = available_balance(tip_jar)?;
+    require!(amount <= available, TipJarError::InsufficientFunds);
+    require!(amount <= tip_jar.release_headroom(), TipJarError::MilestoneNotReleased);
+
+    tip_jar.sub_lamports(amount)?;
+    destination.add_lamports(amount)?;
+    tip_jar.total_withdrawn += amount;
+
+    emit!(Withdrawn {
+        tipjar: tip_jar.key(),
+        destination: destination.key(),
+        amount,
+        remaining: available - amount,
+    });
+
+    Ok(())
+}
+
+/// Gives a treasurer back the cap spent on a cancelled withdrawal request.
+/// `delegate_info` must be the requester's delegate PDA; if it was revoked
+/// since, there is nothing left to restore.
+fn restore_treasurer_cap(pending: &PendingWithdrawal, delegate_info: &AccountInfo) -> Result<()> {
+    if !pending.charged_cap {
+        return Ok(());
+    }
+
+    let (address, _) = Pubkey::find_program_address(
+        &[b"delegate", pending.tipjar.as_ref(), pending.requester.as_ref()],
+        &crate::ID,
+    );
+    require_keys_eq!(delegate_info.key(), address, TipJarError::InvalidDelegateAccount);
+    if delegate_info.owner != &crate::ID || delegate_info.data_is_empty() {
+        return Ok(());
+    }
+
+    let mut delegate = Delegate::try_deserialize(&mut &delegate_info.try_borrow_data()?[..])?;
+    delegate.restore_withdrawal(pending.amount);
+    delegate.try_serialize(&mut &mut delegate_info.try_borrow_mut_data()?[..])?;
+
+    Ok(())
+}
+
+/// Pays `amount` tokens from one of the jar's vaults to `destination`,
+/// signed by the TipJar PDA that owns the vault
+fn pay_out_tokens<'info>(
+    tip_jar: &Account<'info, TipJar>,
+    token_program: &AccountInfo<'info>,
+    memo_program: &AccountInfo<'info>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    // Ensure the vault holds enough tokens
+    require!(amount > 0, TipJarError::InvalidAmount);
+    require!(vault.amount >= amount, TipJarError::InsufficientFunds);
+
+    // The vault is owned by the TipJar PDA, so the jar signs the transfer
+    let index = tip_jar.index.to_le_bytes();
+    let bump = [tip_jar.bump];
+    let signer_seeds: &[&[&[u8]]] = &[&[b"tipjar", tip_jar.creator.as_ref(), &index, &bump]];
+
+    transfer_tokens(
+        token_program,
+        memo_program,
+        &vault.to_account_info(),
+        mint,
+        destination,
+        &tip_jar.to_account_info(),
+        signer_seeds,
+        amount,
+        WITHDRAWAL_MEMO,
+        false,
+    )
+}
+
+/// Memo attached to outgoing transfers when the destination requires one
+const WITHDRAWAL_MEMO: &str = "TipJar withdrawal";
+/// Fallback memo for tips sent without a message to a memo-required account
+const DEFAULT_MEMO: &str = "TipJar tip";
+
+/// Returns true when a Token-2022 account has the required-memo extension enabled
+fn memo_required(token_account: &AccountInfo) -> Result<bool> {
+    if *token_account.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+    let data = token_account.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(memo_transfer::memo_required(&state))
+}
+
+/// Returns the transfer fees withheld in a Token-2022 account, zero for classic accounts
+fn withheld_fees(token_account: &AccountInfo) -> Result<u64> {
+    if *token_account.owner != spl_token_2022::ID {
+        return Ok(0);
+    }
+    let data = token_account.try_borrow_data()?;
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+    Ok(state
+        .get_extension::<TransferFeeAmount>()
+        .map(|fee| u64::from(fee.withheld_amount))
+        .unwrap_or(0))
+}
+
+/// Moves tokens with `transfer_checked` through whichever token program owns the mint.
+/// If the destination has the required-memo extension, `memo` is written right
+/// before the transfer so Token-2022 accepts it. With `forward_memo`, a non-empty
+/// `memo` is written for every Token-2022 transfer, not just required ones.
+#[allow(clippy::too_many_arguments)]
+fn transfer_tokens<'info>(
+    token_program: &AccountInfo<'info>,
+    memo_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+    memo: &str,
+    forward_memo: bool,
+) -> Result<()> {
+    let forwarded = forward_memo && !memo.is_empty() && *token_program.key == spl_token_2022::ID;
+    if forwarded || memo_required(to)? {
+        let memo = if memo.is_empty() { DEFAULT_MEMO } else { memo };
+        memo::build_memo(
+            CpiContext::new(memo_program.clone(), BuildMemo {}),
+            memo.as_bytes(),
+        )?;
+    }
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            TransferChecked {
+                from: from.clone(),
+                mint: mint.to_account_info(),
+                to: to.clone(),
+                authority: authority.clone(),
+            },
+            signer_seeds,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+/// Transfers the full balance of each jar vault to `recipient` and closes the vault.
+/// `remaining_accounts` holds (vault, mint, destination) triples, one per tracked mint,
+/// where each destination is a token account owned by `recipient`.
+fn sweep_token_vaults<'info>(
+    tip_jar: &Account<'info, TipJar>,
+    recipient: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    token_2022_program: &AccountInfo<'info>,
+    memo_program: &AccountInfo<'info>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    require!(
+        remaining_accounts.len() == tip_jar.token_totals.len() * 3,
+        TipJarError::MissingTokenVault
+    );
+
+    let index = tip_jar.index.to_le_bytes();
+    let bump = [tip_jar.bump];
+    let signer_seeds: &[&[&[u8]]] = &[&[b"tipjar", tip_jar.creator.as_ref(), &index, &bump]];
+
+    for (accounts, total) in remaining_accounts.chunks(3).zip(tip_jar.token_totals.iter()) {
+        let vault_info = &accounts[0];
+        let mint = InterfaceAccount::<Mint>::try_from(&accounts[1])?;
+        let destination_info = &accounts[2];
+
+        // Vaults must be passed in the same order as the tracked mints
+        require_keys_eq!(mint.key(), total.mint, TipJarError::MissingTokenVault);
+        let token_program = if *accounts[1].owner == spl_token_2022::ID {
+            token_2022_program
+        } else {
+            token_program
+        };
+        require_keys_eq!(
+            vault_info.key(),
+            get_associated_token_address_with_program_id(&tip_jar.key(), &total.mint, &token_program.key()),
+            TipJarError::MissingTokenVault
+        );
+        let vault = InterfaceAccount::<TokenAccount>::try_from(vault_info)?;
+        let destination = InterfaceAccount::<TokenAccount>::try_from(destination_info)?;
+        require_keys_eq!(destination.mint, total.mint, TipJarError::InvalidTokenAccount);
+        require_keys_eq!(destination.owner, recipient.key(), TipJarError::InvalidTokenAccount);
+
+        if vault.amount > 0 {
+            transfer_tokens(
+                token_program,
+                memo_program,
+                vault_info,
+                &mint,
+                destination_info,
+                &tip_jar.to_account_info(),
+                signer_seeds,
+                vault.amount,
+                WITHDRAWAL_MEMO,
+                false,
+            )?;
+        }
+
+        // Token-2022 refuses to close accounts holding withheld transfer fees
+        if withheld_fees(vault_info)? > 0 {
+            token_interface::harvest_withheld_tokens_to_mint(
+                CpiContext::new(
+                    token_program.clone(),
+                    HarvestWithheldTokensToMint {
+                        token_program_id: token_program.clone(),
+                        mint: mint.to_account_info(),
+                    },
+                ),
+                vec![vault_info.clone()],
+            )?;
+        }
+
+        // Close the empty vault and return its rent to the recipient
+        token_interface::close_account(CpiContext::new_with_signer(
+            token_program.clone(),
+            CloseAccount {
+                account: vault_info.clone(),
+                destination: recipient.clone(),
+                authority: tip_jar.to_account_info(),
+            },
+            signer_seeds,
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Moves lamports from a tipper to `destination`. Wallets pay through the
+/// System Program; program-owned sources such as a subscription escrow are
+/// debited directly.
+fn move_lamports<'info>(source: &AccountInfo<'info>, destination: &AccountInfo<'info>, amount: u64) -> Result<()> {
+    if source.owner == &crate::ID {
+        source.sub_lamports(amount)?;
+        destination.add_lamports(amount)?;
+        return Ok(());
+    }
+
+    let ix = anchor_lang::solana_program::system_instruction::transfer(
+        &source.key(),
+        &destination.key(),
+        amount,
+    );
+    anchor_lang::solana_program::program::invoke(&ix, &[source.clone(), destination.clone()])?;
+
+    Ok(())
+}
+
+/// The common path for every SOL tip to an active jar: skims the protocol fee,
+/// records the tip, pays the jar or its split table and emits events.
+/// Callers check the jar's privacy settings first.
+/// `tip.amount` is the gross amount taken from `source`; the amount credited
+/// to the jar after the fee is returned.
+#[allow(clippy::too_many_arguments)]
+fn deliver_sol_tip<'info>(
+    tip_jar: &mut Account<'info, TipJar>,
+    source: &AccountInfo<'info>,
+    config: &ProgramConfig,
+    treasury: &AccountInfo<'info>,
+    tip_record: Option<&mut Account<'info, TipRecord>>,
+    tip_record_bump: Option<u8>,
+    tip_record_payer: Pubkey,
+    tip_history: Option<&AccountLoader<'info, TipHistory>>,
+    split_recipients: &[AccountInfo<'info>],
+    mut tip: Tip,
+) -> Result<u64> {
+    // Skim the protocol fee to the treasury, the jar is credited with the rest
+    let fee = config.fee_for(tip.amount);
+    if fee > 0 {
+        move_lamports(source, treasury, fee)?;
+    }
+    let amount = tip.amount - fee;
+    tip.amount = amount;
+
+    let sender = tip.sender;
+    let memo = tip.memo.clone();
+    let visibility = tip.visibility;
+    let timestamp = tip.timestamp as i64;
+
+    // Store the tip in the jar's history
+    store_tip(tip_jar, tip_record, tip_record_bump, tip_record_payer, tip_history, tip)?;
+
+    if tip_jar.split_on_receive {
+        // Route the tip straight to the beneficiaries, the jar only keeps the accounting
+        require!(
+            split_recipients.len() == tip_jar.splits.len(),
+            TipJarError::InvalidSplitRecipient
+        );
+
+        let shares = tip_jar.split_shares(amount);
+        for ((split, share), recipient) in tip_jar.splits.iter().zip(shares).zip(split_recipients.iter()) {
+            require_keys_eq!(recipient.key(), split.recipient, TipJarError::InvalidSplitRecipient);
+            if share == 0 {
+                continue;
+            }
+
+            move_lamports(source, recipient, share)?;
+
+            emit!(Distributed {
+                tipjar: tip_jar.key(),
+                recipient: split.recipient,
+                bps: split.bps,
+                amount: share,
+            });
+        }
+    } else {
+        move_lamports(source, &tip_jar.to_account_info(), amount)?;
+    }
+
+    // Update the total_received in the TipJar
+    tip_jar.total_received += amount;
+    check_milestones(tip_jar);
+
+    // Emit an event logging the tip info
+    emit!(TipSent {
+        sender,
+        receiver: tip_jar.key(),
+        amount,
+        fee,
+        mint: None,
+        memo,
+        visibility,
+    });
+
+    // Check if goal has been reached in time
+    if tip_jar.total_received >= tip_jar.goal && tip_jar.before_deadline(timestamp) {
+        emit!(GoalReached {
+            tipjar: tip_jar.key(),
+            goal: tip_jar.goal,
+            total_received: tip_jar.total_received,
+        });
+    }
+
+    Ok(amount)
+}
+
+/// Adds a public SOL tip to the sender's running totals for the jar. Anonymous
+/// tips are never linked to a contribution account.
+fn record_contribution(
+    contribution: Option<&mut Account<Contribution>>,
+    bump: Option<u8>,
+    tipjar: Pubkey,
+    sender: Pubkey,
+    amount: u64,
+    visibility: Visibility,
+    now: i64,
+) -> Result<()> {
+    if visibility == Visibility::Anonymous {
+        require!(contribution.is_none(), TipJarError::InvalidContribution);
+        return Ok(());
+    }
+
+    let contribution = contribution.ok_or(TipJarError::InvalidContribution)?;
+    if contribution.tip_count == 0 {
+        contribution.tipjar = tipjar;
+        contribution.sender = sender;
+        contribution.bump = bump.ok_or(TipJarError::InvalidContribution)?;
+    }
+    contribution.record(amount, now);
+
+    Ok(())
+}
+
+/// Marks newly reached milestones and emits `MilestoneReached` for each
+fn check_milestones(tip_jar: &mut Account<TipJar>) {
+    for index in tip_jar.mark_reached_milestones() {
+        let milestone = &tip_jar.milestones[index];
+        emit!(MilestoneReached {
+            tipjar: tip_jar.key(),
+            goal: milestone.target,
+            total_received: tip_jar.total_received,
+            index: index as u8,
+            label: milestone.label.clone(),
+        });
+    }
+}
+
+/// Releases the funds of a reached milestone once the earlier ones are released
+fn apply_milestone_release(tip_jar: &mut Account<TipJar>, index: u8) -> Result<()> {
+    let index = index as usize;
+    require!(index < tip_jar.milestones.len(), TipJarError::InvalidMilestones);
+    require!(
+        index == 0 || tip_jar.milestones[index - 1].released,
+        TipJarError::MilestoneNotReleased
+    );
+
+    let total_received = tip_jar.total_received;
+    let tipjar = tip_jar.key();
+    let milestone = &mut tip_jar.milestones[index];
+    require!(milestone.reached, TipJarError::MilestoneNotReached);
+    require!(!milestone.released, TipJarError::RedundantStatusChange);
+    milestone.released = true;
+
+    emit!(MilestoneReleased {
+        tipjar,
+        goal: milestone.target,
+        total_received,
+        index: index as u8,
+        label: milestone.label.clone(),
+    });
+
+    Ok(())
+}
+
+/// Ends a successful all-or-nothing campaign, shared by `claim_crowdfunding` and its proposal action
+fn apply_crowdfunding_claim<'info>(
+    tip_jar: &mut Account<'info, TipJar>,
+    config: &ProgramConfig,
+    treasury: &AccountInfo<'info>,
+) -> Result<()> {
+    let raised = tip_jar.claim_campaign()?;
+
+    let fee = config.fee_for(raised);
+    if fee > 0 {
+        move_lamports(&tip_jar.to_account_info(), treasury, fee)?;
+    }
+
+    emit!(CrowdfundingClaimed {
+        tipjar: tip_jar.key(),
+        raised,
+        fee,
+    });
+
+    Ok(())
+}
+
+/// Stops tracking a token mint, shared by `forget_token_mint` and its proposal action
+fn apply_forget_token_mint(tip_jar: &mut Account<TipJar>, mint: Pubkey) -> Result<()> {
+    tip_jar.forget_token_mint(&mint)?;
+
+    emit!(TokenMintForgotten {
+        tipjar: tip_jar.key(),
+        mint,
+    });
+
+    Ok(())
+}
+
+/// Switches the jar to a freshly created ring buffer, shared by
+/// `enable_inline_history` and its proposal action
+fn start_inline_history(tip_jar: &mut Account<TipJar>, tip_history: &AccountLoader<TipHistory>) -> Result<()> {
+    require!(tip_jar.history_mode != HistoryMode::Inline, TipJarError::RedundantStatusChange);
+
+    let mut history = tip_history.load_init()?;
+    history.tipjar = tip_jar.key();
+    tip_jar.history_mode = HistoryMode::Inline;
+
+    msg!("Inline tip history enabled with {} slots", TipHistory::CAPACITY);
+
+    Ok(())
+}
+
+/// Sets or clears the goal deadline, shared by `set_goal_deadline` and its proposal action
+fn apply_goal_deadline(tip_jar: &mut Account<TipJar>, deadline: Option<i64>, all_or_nothing: bool, now: i64) -> Result<()> {
+    // A running campaign, or one with pledges left to refund, can't be changed
+    require!(tip_jar.locked_lamports() == 0, TipJarError::CrowdfundingLocked);
+    if let Some(deadline) = deadline {
+        require!(deadline > now, TipJarError::InvalidDeadline);
+    }
+    require!(!all_or_nothing || deadline.is_some(), TipJarError::InvalidDeadline);
+
+    tip_jar.deadline = deadline;
+    tip_jar.crowdfunding = all_or_nothing;
+    tip_jar.pledged_total = 0;
+    tip_jar.pledges_outstanding = 0;
+    if all_or_nothing {
+        tip_jar.campaign_id += 1;
+    }
+
+    emit!(GoalDeadlineSet {
+        tipjar: tip_jar.key(),
+        goal: tip_jar.goal,
+        deadline,
+        all_or_nothing,
+    });
+
+    Ok(())
+}
+
+/// Applies a new withdrawal policy, shared by `set_withdrawal_policy` and its proposal action
+fn apply_withdrawal_policy(tip_jar: &mut Account<TipJar>, policy: WithdrawalPolicy, now: i64) -> Result<()> {
+    let effective_at = tip_jar.set_withdrawal_policy(policy, now)?;
+
+    emit!(WithdrawalPolicyUpdated {
+        tipjar: tip_jar.key(),
+        per_tx_limit: policy.per_tx_limit,
+        window_limit: policy.window_limit,
+        window_duration: policy.window_duration,
+        timelock_duration: policy.timelock_duration,
+        effective_at,
+    });
+
+    Ok(())
+}
+
+/// Writes a delegate grant, shared by `set_delegate` and its proposal action.
+/// Granting again replaces the roles and cap but keeps the amount already withdrawn.
+fn grant_delegate(
+    tip_jar: &Account<TipJar>,
+    delegate_account: &mut Account<Delegate>,
+    delegate_key: Pubkey,
+    roles: u8,
+    withdraw_cap: u64,
+    bump: u8,
+) -> Result<()> {
+    require!(
+        roles != 0 && roles & !Delegate::ALL_ROLES == 0,
+        TipJarError::InvalidDelegateRoles
+    );
+    require_keys_neq!(delegate_key, tip_jar.owner, TipJarError::InvalidDelegateRoles);
+
+    delegate_account.tipjar = tip_jar.key();
+    delegate_account.delegate = delegate_key;
+    delegate_account.granted_by = tip_jar.owner;
+    delegate_account.roles = roles;
+    delegate_account.withdraw_cap = withdraw_cap;
+    delegate_account.bump = bump;
+
+    emit!(DelegateUpdated {
+        tipjar: tip_jar.key(),
+        delegate: delegate_key,
+        roles,
+        withdraw_cap,
+    });
+
+    Ok(())
+}
+
+/// Writes a tip to the jar's history, either into its own TipRecord or into
+/// the inline ring buffer depending on the jar's history mode.
+/// `tip_record_payer` paid the record's rent and gets it back when it is cleared.
+fn store_tip<'info>(
+    tip_jar: &mut Account<'info, TipJar>,
+    tip_record: Option<&mut Account<'info, TipRecord>>,
+    tip_record_bump: Option<u8>,
+    tip_record_payer: Pubkey,
+    tip_history: Option<&AccountLoader<'info, TipHistory>>,
+    tip: Tip,
+) -> Result<()> {
+    tip_jar.total_tips_count += 1;
+
+    // Small SOL tips count toward the totals but don't take a history slot or sequence
+    if tip.mint.is_none() && tip.amount < tip_jar.history_threshold {
+        require!(tip_record.is_none(), TipJarError::InvalidTipRecord);
+        return Ok(());
+    }
+
+    let sequence = tip_jar.next_tip_sequence();
+
+    match tip_jar.history_mode {
+        HistoryMode::Records => {
+            let record = tip_record.ok_or(TipJarError::InvalidTipRecord)?;
+            let bump = tip_record_bump.ok_or(TipJarError::InvalidTipRecord)?;
+            record.fill(tip_jar.key(), sequence, tip, tip_record_payer, bump);
+        }
+        HistoryMode::Inline => {
+            // Inline jars don't create records, reject one rather than leave it empty
+            require!(tip_record.is_none(), TipJarError::InvalidTipRecord);
+            let tip_history = tip_history.ok_or(TipJarError::InvalidTipHistory)?;
+            tip_history.load_mut()?.push(sequence, &tip);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads up to `limit` TipRecords starting at `cursor` and walking in `order`.
+/// `records` must hold the record PDAs for those sequences in the same order;
+/// records that were cleared are skipped.
+fn read_tip_records<'info>(
+    tip_jar: &Account<'info, TipJar>,
+    records: &'info [AccountInfo<'info>],
+    order: HistoryOrder,
+    cursor: Option<u32>,
+    limit: usize,
+) -> Result<TipHistoryPage> {
+    let total = tip_jar.next_sequence;
+    let first = match (order, cursor) {
+        (_, Some(cursor)) => cursor.min(total),
+        (HistoryOrder::OldestFirst, None) => 0,
+        (HistoryOrder::NewestFirst, None) => total.saturating_sub(1),
+    };
+
+    // Sequences this page covers, clipped to the tips that exist
+    let sequences: Vec<u32> = match order {
+        HistoryOrder::OldestFirst => (first..total).take(limit).collect(),
+        HistoryOrder::NewestFirst if total == 0 => vec![],
+        HistoryOrder::NewestFirst => (0..=first.min(total - 1)).rev().take(limit).collect(),
+    };
+    require!(records.len() >= sequences.len(), TipJarError::InvalidTipRecord);
+
+    let mut tips = Vec::with_capacity(sequences.len());
+    for (&sequence, record_info) in sequences.iter().zip(records.iter()) {
+        // Cleared records no longer exist, leave a gap in the page. Only the record's
+        // own address counts as cleared, so a page can't be made to skip live tips.
+        if record_info.data_is_empty() {
+            let (address, _) = Pubkey::find_program_address(
+                &[b"tip", tip_jar.key().as_ref(), &sequence.to_le_bytes()],
+                &crate::ID,
+            );
+            require_keys_eq!(record_info.key(), address, TipJarError::InvalidTipRecord);
+            continue;
+        }
+
+        let record = Account::<TipRecord>::try_from(record_info)?;
+        require_keys_eq!(record.tipjar, tip_jar.key(), TipJarError::InvalidTipRecord);
+        require!(record.sequence == sequence, TipJarError::InvalidTipRecord);
+
+        tips.push(TipHistoryEntry {
+            sequence,
+            tip: record.tip.clone(),
+        });
+    }
+
+    // Point the caller at the first sequence this page did not cover
+    let next_cursor = match (order, sequences.last()) {
+        (HistoryOrder::OldestFirst, Some(&last)) if last + 1 < total => Some(last + 1),
+        (HistoryOrder::NewestFirst, Some(&last)) if last > 0 => Some(last - 1),
+        _ => None,
+    };
+
+    Ok(TipHistoryPage { tips, next_cursor })
+}
+
+/// Maximum number of tips returned by one `get_tip_history` call, keeps the
+/// serialized page inside the 1024 byte return data limit
+pub const MAX_HISTORY_PAGE_SIZE: usize = 5;
+
+// Context struct for creating the global config
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = ProgramConfig::LEN,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,              // Must be the upgrade authority
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ TipJarError::Unauthorized)]
+    pub program: Program<'info, crate::program::Tipjar>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ TipJarError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for admin changes to the global config
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut, has_one = admin, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+// Context struct for initializing a tip jar
+// This defines what accounts are required for the instruction
+#[derive(Accounts)]
+#[instruction(description: String, category: String)]
+pub struct InitializeTipJar<'info> {
+    #[account(
+        init_if_needed,                    // Created alongside the owner's first jar
+        payer = user,
+        space = OwnerRegistry::LEN,
+        seeds = [b"registry", user.key().as_ref()],
+        bump
+    )]
+    pub registry: Account<'info, OwnerRegistry>, // Lists every jar owned by the user
+
+    #[account(
+        init,                              // Create a new account
+        payer = user,                      // User pays for account creation
+        space = TipJar::LEN,               // Allocate space for the account (includes discriminator)
+        seeds = [b"tipjar", user.key().as_ref(), registry.next_index.to_le_bytes().as_ref()], // PDA seeds for deterministic address
+        bump                               // Add bump to ensure unique address
+    )]
+    pub tipjar: Account<'info, TipJar>,    // The account to create
+    
+    #[account(mut)]                        // Mark as mutable because we'll deduct rent
+    pub user: Signer<'info>,               // User must sign the transaction
+    
+    pub system_program: Program<'info, System>, // Required for account creation
+}
+
+// Context struct for sending a tip
+#[derive(Accounts)]
+pub struct SendTip<'info> {
+    #[account(mut)]                        // Mutable because we're updating it
+    pub tipjar: Account<'info, TipJar>,    // The target TipJar to receive the tip
+
+    #[account(mut)]                        // Mutable because we're deducting SOL
+    pub sender: Signer<'info>,             // The user sending the tip
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>, // Protocol fee settings
+
+    /// CHECK: must be empty, the address only holds data while the sender is blocked
+    #[account(
+        seeds = [b"blocked", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub blocked_sender: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"allowlist", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump = allowlist_entry.bump
+    )]
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>, // Only for tips to private jars
+
+    /// CHECK: receives the protocol fee, must match the config
+    #[account(mut, address = config.treasury)]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init,                              // Omitted when the jar is inactive and nothing is recorded
+        payer = sender,
+        space = TipRecord::LEN,
+        seeds = [b"tip", tipjar.key().as_ref(), tipjar.next_sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub tip_record: Option<Account<'info, TipRecord>>, // History entry for this tip
+
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // Ring buffer for inline-history jars
+
+    #[account(
+        init_if_needed,                    // Only for inactive escrow-policy jars and pledges, omitted otherwise
+        payer = sender,
+        space = RefundEscrow::LEN,
+        seeds = [b"refund", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub refund_escrow: Option<Account<'info, RefundEscrow>>, // Holds the tip until it is refunded
+
+    #[account(
+        init_if_needed,                    // Required for public tips, omitted for anonymous and escrowed ones
+        payer = sender,
+        space = Contribution::LEN,
+        seeds = [b"contribution", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub contribution: Option<Account<'info, Contribution>>, // The sender's running totals
+
+    pub system_program: Program<'info, System>, // Required for transferring SOL
+}
+
+// Context struct for sending an SPL token tip
+#[derive(Accounts)]
+pub struct SendTokenTip<'info> {
+    #[account(mut)]                        // Mutable because we're updating the token totals
+    pub tipjar: Account<'info, TipJar>,
+
+    #[account(mut)]                        // Pays for the vault if it doesn't exist yet
+    pub sender: Signer<'info>,
+
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>, // The token being tipped (SPL or Token-2022)
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = sender,
+        token::token_program = token_program,
+    )]
+    pub sender_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        init_if_needed,                    // First tip in a mint creates the vault
+        payer = sender,
+        associated_token::mint = mint,
+        associated_token::authority = tipjar,
+        associated_token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>, // Jar-owned associated token account
+
+    #[account(
+        init,                              // Omitted for jars with inline history
+        payer = sender,
+        space = TipRecord::LEN,
+        seeds = [b"tip", tipjar.key().as_ref(), tipjar.next_sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub tip_record: Option<Account<'info, TipRecord>>, // History entry for this tip
+
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // Ring buffer for inline-history jars
+
+    /// CHECK: must be empty, the address only holds data while the sender is blocked
+    #[account(
+        seeds = [b"blocked", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub blocked_sender: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"allowlist", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump = allowlist_entry.bump
+    )]
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>, // Only for tips to private jars
+
+    pub token_program: Interface<'info, TokenInterface>, // Token or Token-2022, matching the mint
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub memo_program: Program<'info, Memo>, // Used when the vault requires incoming memos
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for claiming back escrowed tips
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    /// CHECK: the escrow's jar, read only if it still exists; required so pledges can't be skipped
+    #[account(mut, address = refund_escrow.tipjar)]
+    pub tipjar: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        has_one = sender,
+        seeds = [b"refund", refund_escrow.tipjar.as_ref(), sender.key().as_ref()],
+        bump = refund_escrow.bump          // Closed unless it still tracks a pledge in play
+    )]
+    pub refund_escrow: Account<'info, RefundEscrow>,
+    #[account(
+        mut,
+        seeds = [b"contribution", refund_escrow.tipjar.as_ref(), sender.key().as_ref()],
+        bump = contribution.bump
+    )]
+    pub contribution: Option<Account<'info, Contribution>>, // Required to refund public pledges
+    #[account(mut)]
+    pub sender: Signer<'info>,
+}
+
+// Context struct for settling a pledge of a failed campaign
+#[derive(Accounts)]
+pub struct SettleFailedPledge<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        seeds = [b"refund", tipjar.key().as_ref(), refund_escrow.sender.as_ref()],
+        bump = refund_escrow.bump
+    )]
+    pub refund_escrow: Account<'info, RefundEscrow>,
+    #[account(
+        mut,
+        seeds = [b"contribution", tipjar.key().as_ref(), refund_escrow.sender.as_ref()],
+        bump = contribution.bump
+    )]
+    pub contribution: Option<Account<'info, Contribution>>, // Required for public pledges
+}
+
+// Context struct for toggling privacy and setting the Merkle allowlist
+#[derive(Accounts)]
+pub struct SetPrivacy<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+}
+
+// Context struct for adding an allowlist member
+#[derive(Accounts)]
+#[instruction(member: Pubkey)]
+pub struct AddToAllowlist<'info> {
+    #[account(has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init,
+        payer = owner,
+        space = AllowlistEntry::LEN,
+        seeds = [b"allowlist", tipjar.key().as_ref(), member.as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for removing an allowlist member
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        seeds = [b"allowlist", tipjar.key().as_ref(), allowlist_entry.member.as_ref()],
+        bump = allowlist_entry.bump,
+        close = owner
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+// Context struct for setting a goal deadline
+#[derive(Accounts)]
+pub struct SetGoalDeadline<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+}
+
+// Context struct for managing milestones
+#[derive(Accounts)]
+pub struct SetMilestones<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+}
+
+// Context struct for claiming a successful campaign
+#[derive(Accounts)]
+pub struct ClaimCrowdfunding<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+    /// CHECK: receives the protocol fee, must match the config
+    #[account(mut, address = config.treasury)]
+    pub treasury: UncheckedAccount<'info>,
+}
+
+// Context struct for starting a subscription
+#[derive(Accounts)]
+pub struct CreateSubscription<'info> {
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init,                              // One subscription per sender and jar
+        payer = sender,
+        space = Subscription::LEN,
+        seeds = [b"subscription", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub sender: Signer<'info>,             // Pays the rent and the prepaid escrow
+    /// CHECK: must be empty, the address only holds data while the sender is blocked
+    #[account(
+        seeds = [b"blocked", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub blocked_sender: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"allowlist", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump = allowlist_entry.bump
+    )]
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>, // Only for subscriptions to private jars
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for paying a due subscription period
+#[derive(Accounts)]
+pub struct CrankSubscription<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        has_one = sender,
+        seeds = [b"subscription", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    /// CHECK: the subscriber, only receives the escrow's rent after the last period
+    #[account(mut)]
+    pub sender: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub cranker: Signer<'info>,            // Anyone, pays the TipRecord rent
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+    /// CHECK: receives the protocol fee, must match the config
+    #[account(mut, address = config.treasury)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = cranker,
+        space = TipRecord::LEN,
+        seeds = [b"tip", tipjar.key().as_ref(), tipjar.next_sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub tip_record: Option<Account<'info, TipRecord>>, // For record-history jars
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // For inline-history jars
+    /// CHECK: must be empty, the address only holds data while the sender is blocked
+    #[account(
+        seeds = [b"blocked", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub blocked_sender: UncheckedAccount<'info>,
+    #[account(
+        seeds = [b"allowlist", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump = allowlist_entry.bump
+    )]
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>, // Only for payments to private jars
+    #[account(
+        init_if_needed,                    // Required for public subscriptions
+        payer = cranker,
+        space = Contribution::LEN,
+        seeds = [b"contribution", tipjar.key().as_ref(), sender.key().as_ref()],
+        bump
+    )]
+    pub contribution: Option<Account<'info, Contribution>>, // The subscriber's running totals
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for funding an anonymous tip voucher
+#[derive(Accounts)]
+#[instruction(commitment: [u8; 32])]
+pub struct FundVoucher<'info> {
+    #[account(
+        init,
+        payer = funder,
+        space = TipVoucher::LEN,
+        seeds = [b"voucher", commitment.as_ref()],
+        bump
+    )]
+    pub voucher: Account<'info, TipVoucher>,
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for relaying a voucher into a jar
+#[derive(Accounts)]
+pub struct RelayTip<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        seeds = [b"voucher", voucher.commitment.as_ref()],
+        bump = voucher.bump,
+        close = relayer                    // The voucher's rent tops up the relayer fee
+    )]
+    pub voucher: Account<'info, TipVoucher>,
+    #[account(mut)]
+    pub relayer: Signer<'info>,            // Anyone, pays the TipRecord rent
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+    /// CHECK: receives the protocol fee, must match the config
+    #[account(mut, address = config.treasury)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = relayer,
+        space = TipRecord::LEN,
+        seeds = [b"tip", tipjar.key().as_ref(), tipjar.next_sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub tip_record: Option<Account<'info, TipRecord>>, // For record-history jars
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // For inline-history jars
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for reclaiming an unrelayed voucher
+#[derive(Accounts)]
+pub struct ReclaimVoucher<'info> {
+    #[account(
+        mut,
+        seeds = [b"voucher", voucher.commitment.as_ref()],
+        bump = voucher.bump,
+        close = refund_to                  // Deposit and rent go back to the funder
+    )]
+    pub voucher: Account<'info, TipVoucher>,
+    /// CHECK: the refund wallet bound in the voucher's commitment
+    #[account(mut)]
+    pub refund_to: UncheckedAccount<'info>,
+}
+
+// Context struct for cancelling a subscription
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(
+        mut,
+        has_one = sender,
+        seeds = [b"subscription", subscription.tipjar.as_ref(), sender.key().as_ref()],
+        bump = subscription.bump,
+        close = sender                     // Unspent escrow and rent go back to the sender
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut)]
+    pub sender: Signer<'info>,
+}
+
+// Context struct for getting tip statistics
+#[derive(Accounts)]
+pub struct GetTipStats<'info> {
+    pub tipjar: Account<'info, TipJar>,    // The tip jar to get stats for
+}
+
+// Context struct for reading a page of tip history
+#[derive(Accounts)]
+pub struct GetTipHistory<'info> {
+    pub tipjar: Account<'info, TipJar>,    // The tip jar whose history is read
+    #[account(
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // Only for inline-history jars
+}
+
+// Context struct for clearing tip history
+#[derive(Accounts)]
+pub struct ClearTipHistory<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // Only for inline-history jars
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        mut,
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator, receives the rent of anonymous records
+}
+
+// Context struct for blocking a sender
+#[derive(Accounts)]
+#[instruction(sender: Pubkey)]
+pub struct BlockSender<'info> {
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init,
+        payer = authority,
+        space = BlockedSender::LEN,
+        seeds = [b"blocked", tipjar.key().as_ref(), sender.as_ref()],
+        bump
+    )]
+    pub blocked_sender: Account<'info, BlockedSender>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        mut,
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator, pays the entry's rent
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for unblocking a sender
+#[derive(Accounts)]
+pub struct UnblockSender<'info> {
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        seeds = [b"blocked", tipjar.key().as_ref(), blocked_sender.sender.as_ref()],
+        bump = blocked_sender.bump,
+        close = authority
+    )]
+    pub blocked_sender: Account<'info, BlockedSender>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        mut,
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator
+}
+
+// Context struct for redacting a tip's memo
+#[derive(Accounts)]
+#[instruction(sequence: u32)]
+pub struct RedactTip<'info> {
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar @ TipJarError::InvalidTipRecord,
+        seeds = [b"tip", tipjar.key().as_ref(), sequence.to_le_bytes().as_ref()],
+        bump = tip_record.bump
+    )]
+    pub tip_record: Option<Account<'info, TipRecord>>, // For tips stored as records
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // For inline-history jars
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator
+}
+
+// Context struct for choosing the inactive tip policy
+#[derive(Accounts)]
+pub struct SetInactivePolicy<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator
+}
+
+// Context struct for switching a jar to inline tip history
+#[derive(Accounts)]
+pub struct EnableInlineHistory<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init,                              // Fails if the jar already has a ring buffer
+        payer = owner,
+        space = TipHistory::LEN,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: AccountLoader<'info, TipHistory>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for switching a jar back to per-tip records
+#[derive(Accounts)]
+pub struct DisableInlineHistory<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump,
+        close = owner                      // Rent goes back to the owner
+    )]
+    pub tip_history: AccountLoader<'info, TipHistory>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+// Context struct for toggling tip jar status
+#[derive(Accounts)]
+pub struct ToggleTipJarStatus<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator
+}
+
+// Context struct for updating tip jar details
+#[derive(Accounts)]
+pub struct UpdateTipJar<'info> {
+    #[account(mut, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::EDITOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or an editor
+}
+
+// Context struct for pausing a tip jar
+#[derive(Accounts)]
+pub struct PauseTipJar<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator
+}
+
+// Context struct for resuming a tip jar
+#[derive(Accounts)]
+pub struct ResumeTipJar<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::MODERATOR) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a moderator
+}
+
+// Context struct for withdrawing tips
+#[derive(Accounts)]
+pub struct WithdrawTip<'info> {
+    #[account(mut, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut, seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        mut, constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::TREASURER) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a treasurer
+    /// CHECK: any account chosen by the signer, it only receives lamports
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for requesting a timelocked withdrawal
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init,
+        payer = authority,
+        space = PendingWithdrawal::LEN,
+        seeds = [b"withdrawal", tipjar.key().as_ref(), tipjar.next_withdrawal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    #[account(
+        mut, seeds = [b"delegate", tipjar.key().as_ref(), authority.key().as_ref()],
+        bump = delegate.bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // Only when a delegate signs
+    #[account(
+        mut, constraint = tipjar.is_authorized(&authority.key(), delegate.as_deref(), Delegate::TREASURER) @ TipJarError::Unauthorized
+    )]
+    pub authority: Signer<'info>,          // Owner or a treasurer, pays the request's rent
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for executing a timelocked withdrawal
+#[derive(Accounts)]
+pub struct ExecuteWithdrawal<'info> {
+    #[account(mut)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        has_one = destination,
+        seeds = [b"withdrawal", tipjar.key().as_ref(), pending_withdrawal.id.to_le_bytes().as_ref()],
+        bump = pending_withdrawal.bump,
+        close = requester                  // Rent goes back to whoever made the request
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    /// CHECK: must match the destination fixed in the request
+    #[account(mut)]
+    pub destination: UncheckedAccount<'info>,
+    /// CHECK: the owner or treasurer that made the request, only receives its rent
+    #[account(mut, address = pending_withdrawal.requester)]
+    pub requester: UncheckedAccount<'info>,
+}
+
+// Context struct for cancelling a timelocked withdrawal
+#[derive(Accounts)]
+pub struct CancelWithdrawal<'info> {
+    #[account(has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        seeds = [b"withdrawal", tipjar.key().as_ref(), pending_withdrawal.id.to_le_bytes().as_ref()],
+        bump = pending_withdrawal.bump,
+        close = requester                  // Rent goes back to whoever made the request
+    )]
+    pub pending_withdrawal: Account<'info, PendingWithdrawal>,
+    pub owner: Signer<'info>,
+    /// CHECK: the owner or treasurer that made the request, only receives its rent
+    #[account(mut, address = pending_withdrawal.requester)]
+    pub requester: UncheckedAccount<'info>,
+    /// CHECK: the requester's delegate PDA, checked in `restore_treasurer_cap`
+    #[account(mut)]
+    pub delegate: UncheckedAccount<'info>,
+}
+
+// Context struct for updating withdrawal limits
+#[derive(Accounts)]
+pub struct SetWithdrawalPolicy<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+}
+
+// Context struct for withdrawing SPL tokens
+#[derive(Accounts)]
+pub struct WithdrawTokenTip<'info> {
+    #[account(has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = tipjar,
+        associated_token::token_program = token_program,
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>, // Any token account for the mint
+    pub token_program: Interface<'info, TokenInterface>,
+    pub memo_program: Program<'info, Memo>,
+}
+
+// Context struct for forgetting a tracked token mint
+#[derive(Accounts)]
+pub struct ForgetTokenMint<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
+}
+
+// Context struct for closing a tip jar
+#[derive(Accounts)]
+pub struct CloseTipJar<'info> {
+    #[account(
+        mut,
+        has_one = owner,
+        constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired,
+        close = owner                      // close = owner transfers rent to owner
+    )]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        seeds = [b"registry", owner.key().as_ref()],
+        bump = registry.bump,
+    )]
+    pub registry: Account<'info, OwnerRegistry>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,   // Needed to sweep classic SPL vaults
+    pub token_2022_program: Program<'info, Token2022>, // Needed to sweep Token-2022 vaults
+    pub memo_program: Program<'info, Memo>,
+    pub system_program: Program<'info, System>,
+}
+
+// Context struct for granting roles to a delegate
+#[derive(Accounts)]
+#[instruction(delegate_key: Pubkey)]
+pub struct SetDelegate<'info> {
+    #[account(has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init_if_needed,                    // Granting again replaces the roles
+        payer = owner,
+        space = Delegate::LEN,
+        seeds = [b"delegate", tipjar.key().as_ref(), delegate_key.as_ref()],
+        bump
+    )]
+    pub delegate: Account<'info, Delegate>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
-// Context struct for initializing a tip jar
-// This defines what accounts are required for the instruction
+// Context struct for revoking a delegate
 #[derive(Accounts)]
-#[instruction(description: String, category: String)]
-pub struct InitializeTipJar<'info> {
+pub struct RevokeDelegate<'info> {
+    #[account(has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
     #[account(
-        init,                              // Create a new account
-        payer = user,                      // User pays for account creation
-        space = 8 + TipJar::LEN,           // Allocate space for the account
-        seeds = [b"tipjar", user.key().as_ref()], // PDA seeds for deterministic address
-        bump                               // Add bump to ensure unique address
+        mut,
+        has_one = tipjar,
+        seeds = [b"delegate", tipjar.key().as_ref(), delegate.delegate.as_ref()],
+        bump = delegate.bump,
+        close = owner
     )]
-    pub tipjar: Account<'info, TipJar>,    // The account to create
-    
-    #[account(mut)]                        // Mark as mutable because we'll deduct rent
-    pub user: Signer<'info>,               // User must sign the transaction
-    
-    pub system_program: Program<'info, System>, // Required for account creation
+    pub delegate: Account<'info, Delegate>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
 }
 
-// Context struct for sending a tip
+// Context struct for setting a jar's split table
 #[derive(Accounts)]
-#[instruction(memo: String)]
-pub struct SendTip<'info> {
-    #[account(mut)]                        // Mutable because we're updating it
-    pub tipjar: Account<'info, TipJar>,    // The target TipJar to receive the tip
-
-    #[account(mut)]                        // Mutable because we're deducting SOL
-    pub sender: Signer<'info>,             // The user sending the tip
-
-    pub system_program: Program<'info, System>, // Required for transferring SOL
+pub struct SetSplits<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
+    pub tipjar: Account<'info, TipJar>,
+    pub owner: Signer<'info>,
 }
 
-// Context struct for getting tip statistics
+// Context struct for paying out a jar's split table
 #[derive(Accounts)]
-pub struct GetTipStats<'info> {
-    pub tipjar: Account<'info, TipJar>,    // The tip jar to get stats for
+pub struct Distribute<'info> {
+    #[account(mut)]                        // Anyone can trigger a distribution
+    pub tipjar: Account<'info, TipJar>,
 }
 
-// Context struct for clearing tip history
+// Context struct for proposing an ownership transfer
 #[derive(Accounts)]
-pub struct ClearTipHistory<'info> {
-    #[account(mut, has_one = owner)]       // Mutable with owner validation
+pub struct ProposeOwnerTransfer<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
     pub tipjar: Account<'info, TipJar>,
-    #[account(mut)]
-    pub owner: Signer<'info>,              // Owner must sign the transaction
+    pub owner: Signer<'info>,
 }
 
-// Context struct for toggling tip jar status
+// Context struct for accepting an ownership transfer
 #[derive(Accounts)]
-pub struct ToggleTipJarStatus<'info> {
-    #[account(mut, has_one = owner)]       // has_one ensures the owner field matches
+pub struct AcceptOwnerTransfer<'info> {
+    #[account(
+        mut,
+        constraint = tipjar.pending_owner == Some(new_owner.key()) @ TipJarError::NoPendingOwnerTransfer
+    )]
     pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        seeds = [b"registry", tipjar.owner.as_ref()],
+        bump = previous_registry.bump
+    )]
+    pub previous_registry: Account<'info, OwnerRegistry>,
+    #[account(
+        init_if_needed,                    // The new owner may not have a registry yet
+        payer = new_owner,
+        space = OwnerRegistry::LEN,
+        seeds = [b"registry", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_registry: Account<'info, OwnerRegistry>,
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub new_owner: Signer<'info>,          // Must sign to accept
+    pub system_program: Program<'info, System>,
 }
 
-// Context struct for updating tip jar details
+// Context struct for cancelling an ownership transfer
 #[derive(Accounts)]
-pub struct UpdateTipJar<'info> {
+pub struct CancelOwnerTransfer<'info> {
     #[account(mut, has_one = owner)]
     pub tipjar: Account<'info, TipJar>,
-    #[account(mut)]
     pub owner: Signer<'info>,
 }
 
-// Context struct for pausing a tip jar
+// Context struct for handing a jar to a multisig owner set
 #[derive(Accounts)]
-pub struct PauseTipJar<'info> {
-    #[account(mut, has_one = owner)]
+pub struct SetOwnerSet<'info> {
+    #[account(mut, has_one = owner, constraint = !tipjar.is_multisig() @ TipJarError::MultisigRequired)]
     pub tipjar: Account<'info, TipJar>,
-    #[account(mut)]
     pub owner: Signer<'info>,
 }
 
-// Context struct for resuming a tip jar
+// Context struct for creating a multisig proposal
 #[derive(Accounts)]
-pub struct ResumeTipJar<'info> {
-    #[account(mut, has_one = owner)]
+pub struct CreateProposal<'info> {
+    #[account(
+        mut,
+        constraint = tipjar.is_multisig() @ TipJarError::NotMultisig,
+        constraint = tipjar.is_signer(&proposer.key()) @ TipJarError::Unauthorized,
+    )]
     pub tipjar: Account<'info, TipJar>,
+    #[account(
+        init,
+        payer = proposer,
+        space = Proposal::LEN,
+        seeds = [b"proposal", tipjar.key().as_ref(), tipjar.next_proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub proposer: Signer<'info>,           // Must be a member of the owner set
+    pub system_program: Program<'info, System>,
 }
 
-// Context struct for withdrawing tips
+// Context struct for approving a multisig proposal
 #[derive(Accounts)]
-pub struct WithdrawTip<'info> {
-    #[account(mut, has_one = owner)]
+pub struct ApproveProposal<'info> {
+    #[account(constraint = tipjar.is_signer(&approver.key()) @ TipJarError::Unauthorized)]
+    pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        seeds = [b"proposal", tipjar.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    pub approver: Signer<'info>,           // Must be a member of the owner set
+}
+
+// Context struct for cancelling a multisig proposal
+#[derive(Accounts)]
+pub struct CancelProposal<'info> {
     pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        has_one = proposer,
+        seeds = [b"proposal", tipjar.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        close = proposer
+    )]
+    pub proposal: Account<'info, Proposal>,
     #[account(mut)]
-    pub owner: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub proposer: Signer<'info>,
 }
 
-// Context struct for closing a tip jar
+// Context struct for executing a multisig proposal
+// Only the optional accounts used by the proposal's action need to be passed
 #[derive(Accounts)]
-pub struct CloseTipJar<'info> {
-    #[account(mut, has_one = owner, close = owner)] // close = owner transfers rent to owner
+pub struct ExecuteProposal<'info> {
+    #[account(
+        mut,
+        constraint = tipjar.is_multisig() @ TipJarError::NotMultisig,
+        constraint = tipjar.is_signer(&executor.key()) @ TipJarError::Unauthorized,
+    )]
     pub tipjar: Account<'info, TipJar>,
+    #[account(
+        mut,
+        has_one = tipjar,
+        has_one = proposer,
+        seeds = [b"proposal", tipjar.key().as_ref(), proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        close = proposer                   // Executed proposals are closed
+    )]
+    pub proposal: Account<'info, Proposal>,
+    /// CHECK: receives the proposal's rent, must match `proposal.proposer`
     #[account(mut)]
-    pub owner: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub proposer: UncheckedAccount<'info>,
+    pub executor: Signer<'info>,           // Must be a member of the owner set
+    /// CHECK: recipient for Withdraw and Close, or the requester for CancelWithdrawal, checked against the proposal
+    #[account(mut)]
+    pub destination: Option<UncheckedAccount<'info>>,
+    #[account(
+        mut,
+        seeds = [b"registry", tipjar.owner.as_ref()],
+        bump = registry.bump
+    )]
+    pub registry: Option<Account<'info, OwnerRegistry>>, // For Close
+    #[account(mint::token_program = token_program)]
+    pub mint: Option<InterfaceAccount<'info, Mint>>, // For WithdrawToken
+    #[account(mut, token::authority = tipjar)]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>, // For WithdrawToken
+    #[account(mut)]
+    pub token_destination: Option<InterfaceAccount<'info, TokenAccount>>, // For WithdrawToken
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub token_2022_program: Option<Interface<'info, TokenInterface>>, // For Close
+    pub memo_program: Option<Program<'info, Memo>>,
+    #[account(
+        init_if_needed,                    // Granting again replaces the roles
+        payer = payer,
+        space = Delegate::LEN,
+        seeds = [b"delegate", tipjar.key().as_ref(), proposal.action.delegate_key().as_ref()],
+        bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>, // For SetDelegate and RevokeDelegate
+    #[account(
+        init_if_needed,                    // Created when switching to inline history
+        payer = payer,
+        space = TipHistory::LEN,
+        seeds = [b"history", tipjar.key().as_ref()],
+        bump
+    )]
+    pub tip_history: Option<AccountLoader<'info, TipHistory>>, // For SetInlineHistory
+    #[account(
+        init_if_needed,                    // Adding a member again keeps their entry
+        payer = payer,
+        space = AllowlistEntry::LEN,
+        seeds = [b"allowlist", tipjar.key().as_ref(), proposal.action.allowlist_member().as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>, // For AddToAllowlist and RemoveFromAllowlist
+    #[account(
+        mut,
+        has_one = tipjar,
+        seeds = [b"withdrawal", tipjar.key().as_ref(), pending_withdrawal.id.to_le_bytes().as_ref()],
+        bump = pending_withdrawal.bump
+    )]
+    pub pending_withdrawal: Option<Account<'info, PendingWithdrawal>>, // For CancelWithdrawal
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Account<'info, ProgramConfig>>, // For ClaimCrowdfunding
+    /// CHECK: receives the protocol fee, checked against the config
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>, // For ClaimCrowdfunding
+    #[account(mut)]
+    pub payer: Option<Signer<'info>>,      // Funds a new delegate, allowlist entry or ring buffer
+    pub system_program: Option<Program<'info, System>>, // For actions that create or close accounts
 }
 
 // Event emitted when a tip is sent
 #[event]
 pub struct TipSent {
-    pub sender: Pubkey,                    // Address of the person sending the tip
+    pub sender: Pubkey,                    // Address of the person sending the tip, zeroed for anonymous tips
     pub receiver: Pubkey,                  // Address of the TipJar
-    pub amount: u64,                       // Amount of SOL sent
+    pub amount: u64,                       // Amount credited to the jar (lamports or token base units)
+    pub fee: u64,                          // Protocol fee sent to the treasury, in lamports
+    pub mint: Option<Pubkey>,              // Token mint, None for SOL tips
     pub memo: String,                      // Message attached to the tip
     pub visibility: Visibility,            // Whether the tip is public or anonymous
 }
 
+// Event emitted when SOL is withdrawn from a tip jar
+#[event]
+pub struct Withdrawn {
+    pub tipjar: Pubkey,
+    pub destination: Pubkey,               // Account that received the lamports
+    pub amount: u64,                       // Lamports withdrawn
+    pub remaining: u64,                    // Withdrawable lamports left above rent
+}
+
+// Event emitted when withdrawal limits change or a looser change is queued
+#[event]
+pub struct WithdrawalPolicyUpdated {
+    pub tipjar: Pubkey,
+    pub per_tx_limit: u64,
+    pub window_limit: u64,
+    pub window_duration: i64,
+    pub timelock_duration: i64,
+    pub effective_at: i64,                 // When the new limits apply
+}
+
+// Event emitted when a timelocked withdrawal is requested
+#[event]
+pub struct WithdrawalRequested {
+    pub tipjar: Pubkey,
+    pub id: u32,
+    pub amount: u64,
+    pub destination: Pubkey,
+    pub unlock_at: i64,                    // Earliest time the withdrawal can execute
+}
+
+// Event emitted when a timelocked withdrawal is cancelled
+#[event]
+pub struct WithdrawalCancelled {
+    pub tipjar: Pubkey,
+    pub id: u32,
+    pub amount: u64,
+}
+
+// Event emitted for each recipient paid by a distribution
+#[event]
+pub struct Distributed {
+    pub tipjar: Pubkey,
+    pub recipient: Pubkey,
+    pub bps: u16,
+    pub amount: u64,
+}
+
+// Event emitted when a jar changes hands
+#[event]
+pub struct OwnershipTransferred {
+    pub tipjar: Pubkey,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+// Event emitted when a delegate's roles are granted, changed or revoked
+#[event]
+pub struct DelegateUpdated {
+    pub tipjar: Pubkey,
+    pub delegate: Pubkey,
+    pub roles: u8,                         // Zero once revoked
+    pub withdraw_cap: u64,
+}
+
+// Event emitted when the global config changes
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+}
+
+// Event emitted when a jar's multisig owner set changes
+#[event]
+pub struct OwnerSetChanged {
+    pub tipjar: Pubkey,
+    pub signers: Vec<Pubkey>,
+    pub threshold: u8,
+}
+
+// Event emitted when a multisig proposal is created
+#[event]
+pub struct ProposalCreated {
+    pub tipjar: Pubkey,
+    pub id: u32,
+    pub proposer: Pubkey,
+}
+
+// Event emitted when a member approves a proposal
+#[event]
+pub struct ProposalApproved {
+    pub tipjar: Pubkey,
+    pub id: u32,
+    pub approver: Pubkey,
+    pub approvals: u8,                     // Approvals from current members so far
+}
+
+// Event emitted when a proposal executes
+#[event]
+pub struct ProposalExecuted {
+    pub tipjar: Pubkey,
+    pub id: u32,
+}
+
 // Event emitted when a tip jar status changes
 #[event]
 pub struct TipJarStatusChanged {
@@ -453,15 +3631,125 @@ pub struct GoalReached {
     pub total_received: u64,
 }
 
+// Event emitted when tips cover a milestone's target, extends GoalReached
+#[event]
+pub struct MilestoneReached {
+    pub tipjar: Pubkey,
+    pub goal: u64,                         // The milestone's target
+    pub total_received: u64,
+    pub index: u8,
+    pub label: String,
+}
+
+// Event emitted when the owner releases a milestone's funds, extends GoalReached
+#[event]
+pub struct MilestoneReleased {
+    pub tipjar: Pubkey,
+    pub goal: u64,                         // The milestone's target, now the withdrawal cap
+    pub total_received: u64,
+    pub index: u8,
+    pub label: String,
+}
+
+// Event emitted when a tip to an inactive jar is held for refund
+#[event]
+pub struct TipEscrowed {
+    pub tipjar: Pubkey,
+    pub sender: Pubkey,                    // Zeroed for anonymous tips
+    pub lamports: u64,
+    pub timestamp: u64,
+}
+
 // Event emitted when a tip is refunded
 #[event]
 pub struct TipRefunded {
     pub tipjar: Pubkey,
-    pub sender: Pubkey,
+    pub sender: Pubkey,                    // Always shown, the escrow is derived from the sender
     pub lamports: u64,
     pub timestamp: u64,
 }
 
+// Event emitted when an unrelayed voucher is refunded
+#[event]
+pub struct VoucherReclaimed {
+    pub voucher: Pubkey,
+    pub lamports: u64,                     // Tip amount and relayer fee, excluding rent
+}
+
+// Event emitted when a private jar's allowlist changes
+#[event]
+pub struct AllowlistUpdated {
+    pub tipjar: Pubkey,
+    pub member: Pubkey,
+    pub allowed: bool,                     // False once removed
+}
+
+// Event emitted when a sender is blocked or unblocked
+#[event]
+pub struct BlocklistUpdated {
+    pub tipjar: Pubkey,
+    pub sender: Pubkey,
+    pub blocked: bool,                     // False once unblocked
+}
+
+// Event emitted when a tip's memo is blanked
+#[event]
+pub struct TipRedacted {
+    pub tipjar: Pubkey,
+    pub sequence: u32,                     // Position of the tip in the jar's history
+    pub redacted_by: Pubkey,               // Owner or moderator that redacted it
+}
+
+// Event emitted when a private jar's Merkle allowlist is replaced
+#[event]
+pub struct AllowlistRootSet {
+    pub tipjar: Pubkey,
+    pub root: Option<[u8; 32]>,            // None disables proof-based access
+}
+
+// Event emitted when a jar stops tracking a token mint
+#[event]
+pub struct TokenMintForgotten {
+    pub tipjar: Pubkey,
+    pub mint: Pubkey,
+}
+
+// Event emitted when a goal deadline is set or cleared
+#[event]
+pub struct GoalDeadlineSet {
+    pub tipjar: Pubkey,
+    pub goal: u64,
+    pub deadline: Option<i64>,
+    pub all_or_nothing: bool,
+}
+
+// Event emitted when the owner claims a successful campaign
+#[event]
+pub struct CrowdfundingClaimed {
+    pub tipjar: Pubkey,
+    pub raised: u64,                       // Lamports pledged to the campaign
+    pub fee: u64,                          // Protocol fee sent to the treasury
+}
+
+// Event emitted when a recurring tip is set up
+#[event]
+pub struct SubscriptionCreated {
+    pub tipjar: Pubkey,
+    pub sender: Pubkey,                    // Zeroed for anonymous subscriptions
+    pub amount: u64,                       // Lamports per period
+    pub interval: i64,
+    pub periods: u32,
+}
+
+// Event emitted when a subscription is cancelled and refunded
+#[event]
+pub struct SubscriptionCancelled {
+    pub tipjar: Pubkey,
+    pub sender: Pubkey,                    // Zeroed for anonymous subscriptions
+    pub refunded_periods: u32,
+    pub lamports: u64,                     // Escrow returned, excluding rent
+}
+
 // Event emitted for tip jar statistics
 #[event]
 pub struct TipJarStats {
@@ -473,7 +3761,7 @@ pub struct TipJarStats {
 }
 
 // Error enum for the program
-#[error]
+#[error_code]
 pub enum TipJarError {
     #[msg("The TipJar is currently inactive")]
     InactiveTipJar,
@@ -493,7 +3781,7 @@ pub enum TipJarError {
     #[msg("Transaction failed due to an unexpected condition")]
     UnexpectedTransactionFailure,
     
-    #[msg("Amount exceeds withdrawal limit of 1000 SOL")]
+    #[msg("Amount exceeds the tip jar's per-withdrawal limit")]
     WithdrawalLimitExceeded,
 
     #[msg("Memo is too long (maximum 100 characters)")]
@@ -519,4 +3807,160 @@ pub enum TipJarError {
     
     #[msg("Operation not allowed during active tips")]
     OperationDuringActiveTips,
+
+    #[msg("Tip jar already tracks the maximum number of token mints")]
+    TooManyTokenMints,
+
+    #[msg("A token vault for a tracked mint is missing or out of order")]
+    MissingTokenVault,
+
+    #[msg("Token account does not match the expected mint or owner")]
+    InvalidTokenAccount,
+
+    #[msg("Owner already has the maximum number of open tip jars")]
+    TooManyTipJars,
+
+    #[msg("Tip record account is missing or does not belong to this tip jar")]
+    InvalidTipRecord,
+
+    #[msg("History page size must be between 1 and 5 tips")]
+    HistoryPageTooLarge,
+
+    #[msg("Inline tip history account is missing for this tip jar")]
+    InvalidTipHistory,
+
+    #[msg("Refund escrow account is required to tip an inactive jar")]
+    MissingRefundEscrow,
+
+    #[msg("Amount exceeds what is left of the withdrawal window limit")]
+    WindowLimitExceeded,
+
+    #[msg("Withdrawal window must be between one second and a year and the timelock at most a year")]
+    InvalidWithdrawalPolicy,
+
+    #[msg("This tip jar requires timelocked withdrawals")]
+    WithdrawalTimelocked,
+
+    #[msg("The withdrawal timelock has not expired yet")]
+    WithdrawalStillLocked,
+
+    #[msg("This tip jar is controlled by a multisig, use a proposal")]
+    MultisigRequired,
+
+    #[msg("This tip jar does not have a multisig owner set")]
+    NotMultisig,
+
+    #[msg("Owner set must have unique members and a threshold between 1 and its size")]
+    InvalidOwnerSet,
+
+    #[msg("This member has already approved the proposal")]
+    AlreadyApproved,
+
+    #[msg("The proposal does not have enough approvals yet")]
+    NotEnoughApprovals,
+
+    #[msg("Accounts passed do not match the proposal's action")]
+    InvalidProposalAccounts,
+
+    #[msg("Delegate roles must be a non-empty set of known roles for a key other than the owner")]
+    InvalidDelegateRoles,
+
+    #[msg("Withdrawal exceeds the delegate's withdrawal cap")]
+    DelegateCapExceeded,
+
+    #[msg("The new owner must differ from the current owner")]
+    InvalidOwnerTransfer,
+
+    #[msg("No ownership transfer to this wallet is pending")]
+    NoPendingOwnerTransfer,
+
+    #[msg("Splits need unique recipients with non-zero shares adding up to 10,000 basis points")]
+    InvalidSplits,
+
+    #[msg("This tip jar has no split table")]
+    NoSplits,
+
+    #[msg("Recipient accounts must match the split table, in order")]
+    InvalidSplitRecipient,
+
+    #[msg("Protocol fee exceeds the maximum of 1,000 basis points")]
+    FeeTooHigh,
+
+    #[msg("Subscription interval must be between one second and a year")]
+    InvalidSubscription,
+
+    #[msg("The next subscription payment is not due yet")]
+    SubscriptionNotDue,
+
+    #[msg("Every subscription period has already been paid")]
+    SubscriptionComplete,
+
+    #[msg("Deadline must be in the future, and all-or-nothing campaigns need one")]
+    InvalidDeadline,
+
+    #[msg("The campaign's deadline has passed")]
+    CampaignEnded,
+
+    #[msg("Pledges are locked until the campaign is claimed or has failed")]
+    CrowdfundingLocked,
+
+    #[msg("Pledges can only be refunded after the campaign missed its goal")]
+    PledgeLocked,
+
+    #[msg("This tip jar is not running an all-or-nothing campaign")]
+    NotCrowdfunding,
+
+    #[msg("The campaign has not reached its goal")]
+    GoalNotReached,
+
+    #[msg("Milestones need labels of at most 32 bytes and strictly increasing targets")]
+    InvalidMilestones,
+
+    #[msg("Milestones can't be changed while the plan is partly released")]
+    MilestonesLocked,
+
+    #[msg("The milestone has not been reached yet")]
+    MilestoneNotReached,
+
+    #[msg("Funds beyond the last released milestone can't be withdrawn")]
+    MilestoneNotReleased,
+
+    #[msg("Public tips need the sender's contribution account, anonymous and escrowed tips must omit it")]
+    InvalidContribution,
+
+    #[msg("This private tip jar only accepts tips from allowlisted senders")]
+    NotAllowlisted,
+
+    #[msg("The secret doesn't match the voucher's commitment")]
+    InvalidVoucher,
+
+    #[msg("Relayed tips can't go to private jars or all-or-nothing campaigns")]
+    RelayNotAllowed,
+
+    #[msg("This sender has been blocked by the tip jar")]
+    SenderBlocked,
+
+    #[msg("Tip is below this tip jar's minimum")]
+    TipBelowMinimum,
+
+    #[msg("Tip is above this tip jar's maximum")]
+    TipAboveMaximum,
+
+    #[msg("Maximum tip must be at least the minimum and more than zero")]
+    InvalidTipLimits,
+
+    #[msg("All-or-nothing campaigns only accept refundable pledges sent with send_tip")]
+    PledgeRequired,
+
+    #[msg("Split-on-receive can't be combined with a milestone plan")]
+    SplitsWithMilestones,
+
+    #[msg("This tip jar doesn't track the given token mint")]
+    UnknownTokenMint,
+
+    #[msg("Delegate account doesn't belong to the withdrawal's requester")]
+    InvalidDelegateAccount,
+
+    #[msg("Refund escrow must only be passed for tips to inactive jars and pledges")]
+    InvalidRefundEscrow,
 }
\ No newline at end of file
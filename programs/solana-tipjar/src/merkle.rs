@@ -0,0 +1,163 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+// Domain prefixes keep a leaf from ever being passed off as an inner node
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Hash of one allowlisted wallet, the leaf format used by private jars
+pub fn leaf_hash(member: &Pubkey) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, member.as_ref()]).to_bytes()
+}
+
+/// Hashes two nodes in sorted order, so proofs don't need to carry left/right flags
+fn node_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+/// Checks that `leaf` is part of the tree with the given `root`
+pub fn verify(root: &[u8; 32], leaf: &[u8; 32], proof: &[[u8; 32]]) -> bool {
+    let computed = proof.iter().fold(*leaf, |node, sibling| node_hash(&node, sibling));
+    computed == *root
+}
+
+/// Builds an allowlist tree off-chain, e.g. from a token-holder snapshot,
+/// and hands out the proofs senders pass to `send_tip`
+pub struct MerkleTree {
+    // levels[0] holds the sorted leaves, the last level holds the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Builds the tree, duplicate members are only counted once
+    pub fn new(members: &[Pubkey]) -> Self {
+        let mut leaves: Vec<[u8; 32]> = members.iter().map(leaf_hash).collect();
+        leaves.sort_unstable();
+        leaves.dedup();
+
+        let mut levels = vec![leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            // An odd node out is carried up to the next level unchanged
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [a, b] => node_hash(a, b),
+                    [a] => *a,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    /// Root to store on the jar with `set_allowlist_root`, all zeroes for an empty tree
+    pub fn root(&self) -> [u8; 32] {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sibling hashes from the member's leaf up to the root, None if not a member
+    pub fn proof(&self, member: &Pubkey) -> Option<Vec<[u8; 32]>> {
+        let leaf = leaf_hash(member);
+        let mut index = self.levels[0].binary_search(&leaf).ok()?;
+
+        let mut proof = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+
+        Some(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(count: usize) -> Vec<Pubkey> {
+        (0..count).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn proofs_verify_for_every_member() {
+        for count in [1, 2, 3, 4, 5, 7, 8, 13] {
+            let members = members(count);
+            let tree = MerkleTree::new(&members);
+
+            for member in &members {
+                let proof = tree.proof(member).unwrap();
+                assert!(verify(&tree.root(), &leaf_hash(member), &proof), "{count} members");
+            }
+        }
+    }
+
+    #[test]
+    fn single_member_root_is_its_leaf() {
+        let member = Pubkey::new_unique();
+        let tree = MerkleTree::new(&[member]);
+
+        assert_eq!(tree.root(), leaf_hash(&member));
+        assert_eq!(tree.proof(&member), Some(vec![]));
+        assert!(verify(&tree.root(), &leaf_hash(&member), &[]));
+    }
+
+    #[test]
+    fn duplicate_members_are_counted_once() {
+        let members = members(3);
+        let with_duplicates = [members[0], members[1], members[0], members[2], members[2]];
+        let tree = MerkleTree::new(&with_duplicates);
+
+        assert_eq!(tree.root(), MerkleTree::new(&members).root());
+        for member in &with_duplicates {
+            assert!(verify(&tree.root(), &leaf_hash(member), &tree.proof(member).unwrap()));
+        }
+    }
+
+    #[test]
+    fn empty_tree_has_a_zero_root() {
+        let tree = MerkleTree::new(&[]);
+
+        assert_eq!(tree.root(), [0; 32]);
+        assert_eq!(tree.proof(&Pubkey::new_unique()), None);
+    }
+
+    #[test]
+    fn non_members_are_rejected() {
+        let members = members(5);
+        let tree = MerkleTree::new(&members);
+        let outsider = Pubkey::new_unique();
+
+        assert_eq!(tree.proof(&outsider), None);
+        // A member's proof doesn't work for anyone else
+        let proof = tree.proof(&members[0]).unwrap();
+        assert!(!verify(&tree.root(), &leaf_hash(&outsider), &proof));
+    }
+
+    #[test]
+    fn wrong_proofs_are_rejected() {
+        let members = members(6);
+        let tree = MerkleTree::new(&members);
+        let root = tree.root();
+        let leaf = leaf_hash(&members[0]);
+        let proof = tree.proof(&members[0]).unwrap();
+
+        let mut tampered = proof.clone();
+        tampered[0][0] ^= 1;
+        assert!(!verify(&root, &leaf, &tampered));
+
+        assert!(!verify(&root, &leaf, &proof[..proof.len() - 1]));
+        assert!(!verify(&root, &leaf, &tree.proof(&members[1]).unwrap()));
+        assert!(!verify(&MerkleTree::new(&members[1..]).root(), &leaf, &proof));
+    }
+}
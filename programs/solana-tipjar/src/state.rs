@@ -1,4 +1,7 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+use crate::merkle;
+use crate::TipJarError;
 
 /// The main struct that stores all tip jar data on-chain
 /// This is created as PDA owned by the program
@@ -6,24 +9,75 @@ use anchor_lang::prelude::*;
 pub struct TipJar {
     /// whether tips can currently be sent to this jar
     pub is_active: bool,
-    /// whether this jar is private (only owner can send tips)
+    /// whether this jar is private (only the owner and allowlisted senders can tip)
     pub is_private: bool,
+    /// Merkle root of additional allowlisted senders, see the `merkle` module
+    pub allowlist_root: Option<[u8; 32]>,
+    /// what happens to SOL tips sent while the jar is inactive
+    pub inactive_policy: InactiveTipPolicy,
     /// the wallet that owns this tip jar and can withdraw funds
     pub owner: Pubkey,
+    /// the wallet that created this tip jar, part of the PDA seeds
+    pub creator: Pubkey,
+    /// wallet that may accept ownership of this jar, if a transfer is in progress
+    pub pending_owner: Option<Pubkey>,
     /// description of what this tip jar is for
     pub description: String,
     /// category tag for the tip jar (e.g., "content creation", "community", etc.)
     pub category: String,
     /// fundraising goal amount in lamports (1 SOL = 1,000,000,000 lamports)
     pub goal: u64,
+    /// smallest SOL tip accepted, in lamports (0 for no minimum)
+    pub min_tip: u64,
+    /// largest SOL tip accepted, in lamports
+    pub max_tip: Option<u64>,
+    /// SOL tips crediting less than this many lamports count toward the totals
+    /// but aren't written to the tip history
+    pub history_threshold: u64,
     /// total amount of SOL received in lamports
     pub total_received: u64,
-    /// history of recent tips, implemented as a circular buffer
-    pub tips_history: Vec<Tip>,
-    /// current position in the circular buffer
-    pub last_tip_index: u16,
-    /// total count pf all tips ever received (not limited by the buffer size)
+    /// total amount of SOL withdrawn in lamports
+    pub total_withdrawn: u64,
+    /// limits applied to SOL withdrawals
+    pub withdrawal_policy: WithdrawalPolicy,
+    /// looser limits waiting for their delay to pass
+    pub pending_policy: Option<PendingWithdrawalPolicy>,
+    /// recent SOL withdrawals, checked against the policy's rolling window
+    pub withdrawal_window: WithdrawalWindow,
+    /// id used in the seeds of the next pending withdrawal
+    pub next_withdrawal_id: u32,
+    /// members of the owner set for multisig jars, empty for single-owner jars
+    pub signers: Vec<Pubkey>,
+    /// approvals a proposal needs before it can execute, zero for single-owner jars
+    pub threshold: u8,
+    /// id used in the seeds of the next multisig proposal
+    pub next_proposal_id: u32,
+    /// beneficiaries paid by `distribute`, empty when the jar has no split table
+    pub splits: Vec<SplitShare>,
+    /// whether SOL tips go straight to the split table instead of the jar
+    pub split_on_receive: bool,
+    /// unix timestamp by which the goal should be reached, if any
+    pub deadline: Option<i64>,
+    /// whether SOL tips are all-or-nothing pledges until the deadline
+    pub crowdfunding: bool,
+    /// id of the current or most recent all-or-nothing campaign
+    pub campaign_id: u32,
+    /// staged funding targets, withdrawals are capped at the last released one
+    pub milestones: Vec<Milestone>,
+    /// lamports pledged to the current campaign
+    pub pledged_total: u64,
+    /// pledged lamports still held by the jar for the owner or for refunds
+    pub pledges_outstanding: u64,
+    /// running totals for every SPL token mint this jar has been tipped in
+    pub token_totals: Vec<TokenTotal>,
+    /// total count of all tips ever received, including ones kept out of the history
     pub total_tips_count: u32,
+    /// sequence of the next tip written to the history
+    pub next_sequence: u32,
+    /// where tip history is stored, per-tip records or an inline ring buffer
+    pub history_mode: HistoryMode,
+    /// position of this jar in the creator's registry, part of the PDA seeds
+    pub index: u32,
     /// PDA bump used to derive this account's address
     pub bump: u8,
 }
@@ -34,42 +88,1499 @@ impl TipJar {
     const DISCRIMINATOR_LENGTH: usize = 8;
 
    //static fields total size
-    const STATIC_SIZE: usize = 
+    const STATIC_SIZE: usize =
    1 + // is_active
    1 + // is_private
+   1 + 32 + // allowlist_root (Option)
+   1 + // inactive_policy
    32 + // owner (Pubkey)
+   32 + // creator (Pubkey)
+   1 + 32 + // pending_owner (Option<Pubkey>)
    8 + // goal
+   8 + // min_tip
+   1 + 8 + // max_tip (Option)
+   8 + // history_threshold
    8 + // total_received
+   8 + // total_withdrawn
+   WithdrawalPolicy::SIZE + // withdrawal_policy
+   1 + PendingWithdrawalPolicy::SIZE + // pending_policy (Option)
+   WithdrawalWindow::SIZE + // withdrawal_window
+   4 + // next_withdrawal_id
+   4 + (Self::MAX_SIGNERS * 32) + // signers, Vec prefix(4) + members
+   1 + // threshold
+   4 + // next_proposal_id
+   1 + // split_on_receive
+   1 + 8 + // deadline (Option)
+   1 + // crowdfunding
+   4 + // campaign_id
+   8 + // pledged_total
+   8 + // pledges_outstanding
+   4 + // index
    1 + // bump
-   2 + // last_tip_index
-   4; // total_tips_count
+   4 + // total_tips_count
+   4 + // next_sequence
+   1; // history_mode
 
     // dynamic fields calculation
-    const MAX_DESCRIPTION_LEN: usize = 200;
-    const MAX_CATEGORY_LEN: usize = 100;
-    // Maximum number of tips to store in history
-    pub const MAX_TIPS_HISTORY_LEN: usize = 100; // reduced for efficient space usage
+    pub const MAX_DESCRIPTION_LEN: usize = 200;
+    pub const MAX_CATEGORY_LEN: usize = 100;
+    // Maximum number of distinct SPL token mints tracked per jar
+    pub const MAX_TOKEN_MINTS: usize = 10;
+    // Maximum number of members in a multisig owner set
+    pub const MAX_SIGNERS: usize = 10;
+    // Maximum number of beneficiaries in a split table
+    pub const MAX_SPLITS: usize = 10;
+    // Maximum number of funding milestones
+    pub const MAX_MILESTONES: usize = 5;
 
     /// Calculates the total space needed for this account
-    pub fn space() -> usize {
-        Self::DISCRIMINATOR_SIZE + // account discriminator
+    pub const fn space() -> usize {
+        Self::DISCRIMINATOR_LENGTH + // account discriminator
         Self::STATIC_SIZE + // static fields
         4 + Self::MAX_DESCRIPTION_LEN + // String prefix(4) + max chars description
         4 + Self::MAX_CATEGORY_LEN + // String prefix(4) + max chars category
-        4 + (Self::MAX_TIP_HISTORY_LEN * Tip::SIZE) // Vec prefix(4) + entries
+        4 + (Self::MAX_TOKEN_MINTS * TokenTotal::SIZE) + // Vec prefix(4) + per-mint totals
+        4 + (Self::MAX_SPLITS * SplitShare::SIZE) + // Vec prefix(4) + split table
+        4 + (Self::MAX_MILESTONES * Milestone::SIZE) // Vec prefix(4) + milestones
     }
 
     // total length constant used in account initialization
     pub const LEN: usize = Self::space();
+
+    /// True once the jar is controlled by an M-of-N owner set
+    pub fn is_multisig(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// True if `key` belongs to the multisig owner set
+    pub fn is_signer(&self, key: &Pubkey) -> bool {
+        self.signers.contains(key)
+    }
+
+    /// True if `authority` is the owner of a single-owner jar, or holds `role`
+    /// through a delegate account granted for the current owner
+    pub fn is_authorized(&self, authority: &Pubkey, delegate: Option<&Delegate>, role: u8) -> bool {
+        (!self.is_multisig() && self.owner == *authority)
+            || delegate.is_some_and(|delegate| delegate.granted_by == self.owner && delegate.has_role(role))
+    }
+
+    /// Validates and applies a new owner set; an empty set with a zero
+    /// threshold turns the jar back into a single-owner jar
+    pub fn set_owner_set(&mut self, signers: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        require!(signers.len() <= Self::MAX_SIGNERS, TipJarError::InvalidOwnerSet);
+        require!(threshold as usize <= signers.len(), TipJarError::InvalidOwnerSet);
+        require!(signers.is_empty() == (threshold == 0), TipJarError::InvalidOwnerSet);
+
+        // Duplicate members would let one key count twice
+        for (i, signer) in signers.iter().enumerate() {
+            require!(!signers[..i].contains(signer), TipJarError::InvalidOwnerSet);
+        }
+
+        self.signers = signers;
+        self.threshold = threshold;
+
+        Ok(())
+    }
+
+    /// Validates and applies a new split table; an empty table removes the splits
+    pub fn set_splits(&mut self, splits: Vec<SplitShare>) -> Result<()> {
+        require!(splits.len() <= Self::MAX_SPLITS, TipJarError::InvalidSplits);
+
+        if !splits.is_empty() {
+            let total_bps: u32 = splits.iter().map(|split| split.bps as u32).sum();
+            require!(total_bps == SplitShare::TOTAL_BPS as u32, TipJarError::InvalidSplits);
+        }
+
+        // Every share must pay someone, and each recipient appears once
+        for (i, split) in splits.iter().enumerate() {
+            require!(split.bps > 0, TipJarError::InvalidSplits);
+            require!(
+                !splits[..i].iter().any(|other| other.recipient == split.recipient),
+                TipJarError::InvalidSplits
+            );
+        }
+
+        // Without recipients there is nothing to route tips to
+        if splits.is_empty() {
+            self.split_on_receive = false;
+        }
+        self.splits = splits;
+
+        Ok(())
+    }
+
+    /// Turns split-on-receive on or off. Tips routed straight to the split table
+    /// would bypass milestone releases, so the two can't be combined.
+    pub fn set_split_on_receive(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            require!(!self.splits.is_empty(), TipJarError::NoSplits);
+            require!(self.milestones.is_empty(), TipJarError::SplitsWithMilestones);
+        }
+        self.split_on_receive = enabled;
+
+        Ok(())
+    }
+
+    /// Divides `amount` between the split table in basis-point proportions,
+    /// with the rounding dust added to the first recipient's share
+    pub fn split_shares(&self, amount: u64) -> Vec<u64> {
+        let mut shares: Vec<u64> = self
+            .splits
+            .iter()
+            .map(|split| (amount as u128 * split.bps as u128 / SplitShare::TOTAL_BPS as u128) as u64)
+            .collect();
+        let paid: u64 = shares.iter().sum();
+        if let Some(first) = shares.first_mut() {
+            *first += amount - paid;
+        }
+        shares
+    }
+
+    /// Lamports that can be withdrawn right now without breaking the withdrawal policy
+    pub fn withdrawal_allowance(&self, now: i64) -> u64 {
+        let policy = self.withdrawal_policy;
+        let withdrawn = self.withdrawal_window.withdrawn(policy.window_duration, now);
+
+        policy.per_tx_limit.min(policy.window_limit.saturating_sub(withdrawn))
+    }
+
+    /// True if `sender` may tip this jar; `allowlisted` says whether the sender
+    /// proved allowlist membership
+    pub fn accepts_tips_from(&self, sender: &Pubkey, allowlisted: bool) -> bool {
+        !self.is_private || *sender == self.owner || allowlisted
+    }
+
+    /// True if `proof` shows `member` is in the jar's Merkle allowlist
+    pub fn in_allowlist_tree(&self, member: &Pubkey, proof: &[[u8; 32]]) -> bool {
+        self.allowlist_root
+            .is_some_and(|root| merkle::verify(&root, &merkle::leaf_hash(member), proof))
+    }
+
+    /// True while the goal can still be reached on time
+    pub fn before_deadline(&self, now: i64) -> bool {
+        match self.deadline {
+            Some(deadline) => now <= deadline,
+            None => true,
+        }
+    }
+
+    /// True once an all-or-nothing campaign missed its goal by the deadline
+    pub fn campaign_failed(&self, now: i64) -> bool {
+        self.crowdfunding && !self.before_deadline(now) && self.pledged_total < self.goal
+    }
+
+    /// Pledged lamports the owner can't withdraw: everything pledged until the
+    /// campaign is claimed, or what is left for refunds after it fails
+    pub fn locked_lamports(&self) -> u64 {
+        if self.crowdfunding {
+            self.pledges_outstanding
+        } else {
+            0
+        }
+    }
+
+    /// True while `escrow` holds a pledge to the campaign this jar is running
+    pub fn has_open_pledge(&self, escrow: &RefundEscrow) -> bool {
+        self.crowdfunding && escrow.campaign_id == self.campaign_id && escrow.pledged > 0
+    }
+
+    /// Books a pledge to the running campaign on the jar and the sender's escrow.
+    /// Pledges left over from an earlier campaign were already settled.
+    pub fn record_pledge(&mut self, escrow: &mut RefundEscrow, amount: u64, visibility: Visibility) {
+        if escrow.campaign_id != self.campaign_id {
+            escrow.pledged = 0;
+            escrow.credited = 0;
+            escrow.credited_count = 0;
+            escrow.campaign_id = self.campaign_id;
+        }
+        escrow.pledged += amount;
+        if visibility == Visibility::Public {
+            escrow.credited += amount;
+            escrow.credited_count += 1;
+        }
+
+        self.total_received += amount;
+        self.pledged_total += amount;
+        self.pledges_outstanding += amount;
+    }
+
+    /// Takes a failed campaign's pledge off the jar's books and out of the sender's
+    /// contribution. Returns the lamports to pay back; the caller moves them out of
+    /// the jar. The last settled pledge ends the campaign.
+    pub fn unwind_pledge(
+        &mut self,
+        escrow: &mut RefundEscrow,
+        contribution: Option<&mut Contribution>,
+        now: i64,
+    ) -> Result<u64> {
+        require!(self.campaign_failed(now), TipJarError::PledgeLocked);
+
+        if escrow.credited > 0 {
+            let contribution = contribution.ok_or(TipJarError::InvalidContribution)?;
+            contribution.total_amount = contribution.total_amount.saturating_sub(escrow.credited);
+            contribution.tip_count = contribution.tip_count.saturating_sub(escrow.credited_count);
+        }
+
+        let refund = escrow.pledged;
+        self.pledges_outstanding -= refund;
+        self.total_received -= refund;
+        self.unmark_unfunded_milestones();
+        if self.pledges_outstanding == 0 {
+            self.crowdfunding = false;
+        }
+
+        escrow.pledged = 0;
+        escrow.credited = 0;
+        escrow.credited_count = 0;
+
+        Ok(refund)
+    }
+
+    /// Moves a failed pledge into the escrow's refundable balance. Returns the
+    /// lamports the caller moves from the jar to the escrow.
+    pub fn settle_pledge(
+        &mut self,
+        escrow: &mut RefundEscrow,
+        contribution: Option<&mut Contribution>,
+        now: i64,
+    ) -> Result<u64> {
+        require!(self.has_open_pledge(escrow), TipJarError::NotCrowdfunding);
+
+        let refund = self.unwind_pledge(escrow, contribution, now)?;
+        escrow.amount += refund;
+
+        Ok(refund)
+    }
+
+    /// Ends a campaign that reached its goal, releasing every pledge to the owner.
+    /// Returns the lamports raised.
+    pub fn claim_campaign(&mut self) -> Result<u64> {
+        require!(self.crowdfunding, TipJarError::NotCrowdfunding);
+        require!(self.pledged_total >= self.goal, TipJarError::GoalNotReached);
+
+        self.crowdfunding = false;
+        self.pledges_outstanding = 0;
+
+        Ok(self.pledged_total)
+    }
+
+    /// Validates and applies a new milestone plan; the last target becomes the goal.
+    /// An empty plan removes the milestones. Targets are cumulative `total_received`
+    /// amounts and must strictly increase.
+    pub fn set_milestones(&mut self, milestones: Vec<MilestoneTarget>) -> Result<()> {
+        require!(milestones.len() <= Self::MAX_MILESTONES, TipJarError::InvalidMilestones);
+        require!(
+            milestones.is_empty() || !self.split_on_receive,
+            TipJarError::SplitsWithMilestones
+        );
+        // Once funds were released against the plan it can't be rewritten until it is complete
+        require!(
+            self.milestones_complete() || !self.milestones.iter().any(|milestone| milestone.released),
+            TipJarError::MilestonesLocked
+        );
+
+        let mut previous = 0;
+        for milestone in milestones.iter() {
+            require!(milestone.label.len() <= Milestone::MAX_LABEL_LEN, TipJarError::InvalidMilestones);
+            require!(milestone.target > previous, TipJarError::InvalidMilestones);
+            previous = milestone.target;
+        }
+
+        if let Some(last) = milestones.last() {
+            require!(
+                last.target == self.goal || self.locked_lamports() == 0,
+                TipJarError::CrowdfundingLocked
+            );
+            self.goal = last.target;
+        }
+
+        self.milestones = milestones
+            .into_iter()
+            .map(|milestone| Milestone {
+                label: milestone.label,
+                target: milestone.target,
+                reached: false,
+                released: false,
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    /// Marks milestones covered by `total_received` as reached and returns their indexes
+    pub fn mark_reached_milestones(&mut self) -> Vec<usize> {
+        let total_received = self.total_received;
+        self.milestones
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, milestone)| !milestone.reached && total_received >= milestone.target)
+            .map(|(index, milestone)| {
+                milestone.reached = true;
+                index
+            })
+            .collect()
+    }
+
+    /// Clears the reached flag of unreleased milestones that `total_received` no
+    /// longer covers, e.g. after pledges were refunded
+    pub fn unmark_unfunded_milestones(&mut self) {
+        let total_received = self.total_received;
+        for milestone in self.milestones.iter_mut() {
+            if !milestone.released && total_received < milestone.target {
+                milestone.reached = false;
+            }
+        }
+    }
+
+    /// True once the last milestone of the plan has been released
+    pub fn milestones_complete(&self) -> bool {
+        self.milestones.last().is_some_and(|milestone| milestone.released)
+    }
+
+    /// Lamports that can still be withdrawn under the milestone plan, unlimited without
+    /// one or once the whole plan was released, so tips above the last target aren't locked
+    pub fn release_headroom(&self) -> u64 {
+        if self.milestones.is_empty() || self.milestones_complete() {
+            return u64::MAX;
+        }
+
+        let released = self
+            .milestones
+            .iter()
+            .rev()
+            .find(|milestone| milestone.released)
+            .map_or(0, |milestone| milestone.target);
+        released.saturating_sub(self.total_withdrawn)
+    }
+
+    /// Validates and applies new metadata
+    pub fn update_metadata(&mut self, description: String, category: String, goal: u64) -> Result<()> {
+        require!(goal > 0, TipJarError::InvalidGoal);
+        // A campaign's goal is fixed until its pledges are settled
+        require!(goal == self.goal || self.locked_lamports() == 0, TipJarError::CrowdfundingLocked);
+        require!(description.len() <= Self::MAX_DESCRIPTION_LEN, TipJarError::DescriptionTooLong);
+        require!(category.len() <= Self::MAX_CATEGORY_LEN, TipJarError::CategoryTooLong);
+
+        self.description = description;
+        self.category = category;
+        self.goal = goal;
+
+        Ok(())
+    }
+
+    /// Sets the SOL tip bounds and the history threshold
+    pub fn set_tip_limits(&mut self, min_tip: u64, max_tip: Option<u64>, history_threshold: u64) -> Result<()> {
+        if let Some(max) = max_tip {
+            require!(max > 0 && max >= min_tip, TipJarError::InvalidTipLimits);
+        }
+
+        self.min_tip = min_tip;
+        self.max_tip = max_tip;
+        self.history_threshold = history_threshold;
+
+        Ok(())
+    }
+
+    /// Checks a SOL tip of `amount` lamports against the jar's bounds
+    pub fn check_tip_amount(&self, amount: u64) -> Result<()> {
+        require!(amount >= self.min_tip, TipJarError::TipBelowMinimum);
+        if let Some(max) = self.max_tip {
+            require!(amount <= max, TipJarError::TipAboveMaximum);
+        }
+        Ok(())
+    }
+
+    /// Reserves the history sequence number for a new tip
+    pub fn next_tip_sequence(&mut self) -> u32 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        sequence
+    }
+
+    /// Promotes a pending policy once its delay has passed
+    pub fn apply_pending_policy(&mut self, now: i64) {
+        if let Some(pending) = self.pending_policy {
+            if now >= pending.effective_at {
+                self.withdrawal_policy = pending.policy;
+                self.pending_policy = None;
+            }
+        }
+    }
+
+    /// Validates a new withdrawal policy, applying it now if it is at least as strict
+    /// and queueing it behind `WithdrawalPolicy::LOOSENING_DELAY` otherwise.
+    /// Returns when the policy takes effect.
+    pub fn set_withdrawal_policy(&mut self, policy: WithdrawalPolicy, now: i64) -> Result<i64> {
+        require!(policy.is_valid(), TipJarError::InvalidWithdrawalPolicy);
+        self.apply_pending_policy(now);
+
+        // A new change always replaces whatever was queued before
+        if self.withdrawal_policy.is_looser(&policy) {
+            let effective_at = now + WithdrawalPolicy::LOOSENING_DELAY;
+            self.pending_policy = Some(PendingWithdrawalPolicy { policy, effective_at });
+            Ok(effective_at)
+        } else {
+            self.withdrawal_policy = policy;
+            self.pending_policy = None;
+            Ok(now)
+        }
+    }
+
+    /// Rejects a payout that skips `request_withdrawal` while the jar has a timelock
+    pub fn require_no_timelock(&mut self, now: i64) -> Result<()> {
+        self.apply_pending_policy(now);
+        require!(self.withdrawal_policy.timelock_duration == 0, TipJarError::WithdrawalTimelocked);
+
+        Ok(())
+    }
+
+    /// Rejects a proposal's payout until the jar's timelock has run since `created_at`
+    pub fn require_timelock_passed(&mut self, created_at: i64, now: i64) -> Result<()> {
+        self.apply_pending_policy(now);
+        require!(
+            now >= created_at + self.withdrawal_policy.timelock_duration,
+            TipJarError::WithdrawalStillLocked
+        );
+
+        Ok(())
+    }
+
+    /// True if closing the jar pays out SOL or sweeps token vaults
+    pub fn close_pays_out(&self, balance: u64) -> bool {
+        balance > 0 || !self.token_totals.is_empty()
+    }
+
+    /// When a withdrawal requested at `now` may execute, under the timelock in force
+    pub fn withdrawal_unlock_at(&mut self, now: i64) -> i64 {
+        self.apply_pending_policy(now);
+        now + self.withdrawal_policy.timelock_duration
+    }
+
+    /// Checks a SOL withdrawal against the per-transaction and window limits
+    /// and counts it toward the current window
+    pub fn record_withdrawal(&mut self, amount: u64, now: i64) -> Result<()> {
+        self.apply_pending_policy(now);
+        let policy = self.withdrawal_policy;
+
+        require!(amount <= policy.per_tx_limit, TipJarError::WithdrawalLimitExceeded);
+
+        let withdrawn = self
+            .withdrawal_window
+            .withdrawn(policy.window_duration, now)
+            .saturating_add(amount);
+        require!(withdrawn <= policy.window_limit, TipJarError::WindowLimitExceeded);
+        self.withdrawal_window.record(policy.window_duration, now, amount);
+
+        Ok(())
+    }
+
+    /// Adds a token tip to the running total for its mint. Anyone can tip any
+    /// mint, so the total saturates rather than letting a huge supply fail tips.
+    pub fn record_token_amount(&mut self, mint: Pubkey, amount: u64) -> Result<()> {
+        if let Some(total) = self.token_totals.iter_mut().find(|t| t.mint == mint) {
+            total.total_received = total.total_received.saturating_add(amount);
+            return Ok(());
+        }
+
+        // First tip in this mint, start tracking it if there is room
+        require!(
+            self.token_totals.len() < Self::MAX_TOKEN_MINTS,
+            TipJarError::TooManyTokenMints
+        );
+        self.token_totals.push(TokenTotal {
+            mint,
+            total_received: amount,
+        });
+
+        Ok(())
+    }
+
+    /// Stops tracking `mint`, freeing its slot and leaving its vault out of the
+    /// close sweep. A later tip in the mint starts tracking it again.
+    pub fn forget_token_mint(&mut self, mint: &Pubkey) -> Result<()> {
+        let position = self
+            .token_totals
+            .iter()
+            .position(|total| total.mint == *mint)
+            .ok_or(TipJarError::UnknownTokenMint)?;
+        self.token_totals.remove(position);
+
+        Ok(())
+    }
+}
+
+/// SOL held for a sender who tipped an inactive jar, derived from
+/// `[b"refund", tipjar, sender]`. The sender reclaims it with `claim_refund`.
+/// Also records the sender's pledges to an all-or-nothing campaign, which are
+/// held by the jar and only refundable if the campaign fails.
+#[account]
+pub struct RefundEscrow {
+    /// the tip jar the tips were meant for
+    pub tipjar: Pubkey,
+    /// the wallet that sent the tips and can claim them back
+    pub sender: Pubkey,
+    /// lamports held for refund, excluding the account's rent
+    pub amount: u64,
+    /// lamports pledged to campaign `campaign_id`, held by the jar
+    pub pledged: u64,
+    /// part of `pledged` credited to the sender's `Contribution` (public pledges)
+    pub credited: u64,
+    /// number of pledges making up `credited`
+    pub credited_count: u32,
+    /// the jar campaign the pledges belong to
+    pub campaign_id: u32,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl RefundEscrow {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    32 + // sender (Pubkey)
+    8 + // amount
+    8 + // pledged
+    8 + // credited
+    4 + // credited_count
+    4 + // campaign_id
+    1; // bump
+
+    /// Empties the held tips while a live pledge keeps the escrow open.
+    /// Returns the lamports the caller pays back to the sender.
+    pub fn release_held_tips(&mut self) -> Result<u64> {
+        require!(self.amount > 0, TipJarError::PledgeLocked);
+        let amount = self.amount;
+        self.amount = 0;
+
+        Ok(amount)
+    }
+}
+
+/// Marks `member` as allowed to tip a private jar, derived from
+/// `[b"allowlist", tipjar, member]`
+#[account]
+pub struct AllowlistEntry {
+    /// the private tip jar
+    pub tipjar: Pubkey,
+    /// the wallet allowed to tip it
+    pub member: Pubkey,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl AllowlistEntry {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    32 + // member (Pubkey)
+    1; // bump
+}
+
+/// Running totals of one sender's public SOL tips to a jar, derived from
+/// `[b"contribution", tipjar, sender]`. Unlike tip history it is never cleared.
+#[account]
+pub struct Contribution {
+    /// the tip jar that was tipped
+    pub tipjar: Pubkey,
+    /// the wallet that sent the tips
+    pub sender: Pubkey,
+    /// lamports credited to the jar from this sender, after protocol fees
+    pub total_amount: u64,
+    /// number of tips from this sender
+    pub tip_count: u32,
+    /// unix timestamp of the sender's first tip
+    pub first_tip_at: i64,
+    /// unix timestamp of the sender's latest tip
+    pub last_tip_at: i64,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl Contribution {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    32 + // sender (Pubkey)
+    8 + // total_amount
+    4 + // tip_count
+    8 + // first_tip_at
+    8 + // last_tip_at
+    1; // bump
+
+    /// Adds a tip of `amount` lamports to the running totals
+    pub fn record(&mut self, amount: u64, now: i64) {
+        if self.tip_count == 0 {
+            self.first_tip_at = now;
+        }
+        self.total_amount += amount;
+        self.tip_count += 1;
+        self.last_tip_at = now;
+    }
+}
+
+/// Marks `sender` as blocked from tipping a jar, derived from
+/// `[b"blocked", tipjar, sender]`. Tip instructions require this address to be empty.
+#[account]
+pub struct BlockedSender {
+    /// the tip jar the sender is blocked from
+    pub tipjar: Pubkey,
+    /// the blocked wallet
+    pub sender: Pubkey,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl BlockedSender {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    32 + // sender (Pubkey)
+    1; // bump
+}
+
+/// SOL set aside for one anonymous tip, derived from `[b"voucher", commitment]`.
+/// It names neither the funder nor the jar, so a relayer can deliver the tip
+/// without the funder's wallet appearing in the jar's data.
+#[account]
+pub struct TipVoucher {
+    /// hash binding the secret to the receiving jar, memo and refund wallet, see `TipVoucher::commit`
+    pub commitment: [u8; 32],
+    /// lamports tipped to the jar
+    pub amount: u64,
+    /// lamports paid to the relayer that delivers the tip
+    pub relayer_fee: u64,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl TipVoucher {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // commitment
+    8 + // amount
+    8 + // relayer_fee
+    1; // bump
+
+    /// Hash of where the voucher is tipped, revealed by `relay_tip`
+    pub fn tip_hash(secret: &[u8; 32], tipjar: &Pubkey, memo: &str) -> [u8; 32] {
+        hashv(&[b"voucher-tip", secret, tipjar.as_ref(), memo.as_bytes()]).to_bytes()
+    }
+
+    /// Hash of where the voucher is refunded, revealed by `reclaim_voucher`
+    pub fn refund_hash(secret: &[u8; 32], refund_to: &Pubkey) -> [u8; 32] {
+        hashv(&[b"voucher-refund", secret, refund_to.as_ref()]).to_bytes()
+    }
+
+    /// Commitment stored on the voucher, computed off-chain by the funder. Each
+    /// path only reveals its own half, so relaying doesn't expose the refund
+    /// wallet and reclaiming doesn't expose the jar.
+    pub fn commit(tip_hash: &[u8; 32], refund_hash: &[u8; 32]) -> [u8; 32] {
+        hashv(&[b"voucher", tip_hash, refund_hash]).to_bytes()
+    }
+}
+
+/// A recurring tip paid from prepaid escrow, derived from
+/// `[b"subscription", tipjar, sender]`. The escrowed lamports live in this
+/// account above its rent; anyone can crank a payment once it is due.
+#[account]
+pub struct Subscription {
+    /// the tip jar being tipped
+    pub tipjar: Pubkey,
+    /// the wallet that funded the subscription and is recorded as the tipper
+    pub sender: Pubkey,
+    /// lamports paid each period
+    pub amount: u64,
+    /// seconds between payments
+    pub interval: i64,
+    /// unix timestamp from which the next payment can be cranked
+    pub next_due: i64,
+    /// payments left, each already funded in escrow
+    pub remaining_periods: u32,
+    /// visibility recorded with each tip
+    pub visibility: Visibility,
+    /// memo recorded with each tip
+    pub memo: String,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl Subscription {
+    // Maximum memo length, same as a regular tip
+    pub const MAX_MEMO_LEN: usize = 100;
+
+    /// Longest interval between payments, one year
+    pub const MAX_INTERVAL: i64 = 365 * 24 * 60 * 60;
+
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    32 + // sender (Pubkey)
+    8 + // amount
+    8 + // interval
+    8 + // next_due
+    4 + // remaining_periods
+    1 + // visibility
+    4 + Self::MAX_MEMO_LEN + // String prefix(4) + memo
+    1; // bump
+}
+
+/// Per-owner index of tip jars, derived from `[b"registry", owner]`
+/// Lets clients enumerate an owner's jars without scanning program accounts
+#[account]
+pub struct OwnerRegistry {
+    /// the wallet whose jars are listed here
+    pub owner: Pubkey,
+    /// index used in the seeds of the next jar this owner creates
+    pub next_index: u32,
+    /// addresses of the owner's open tip jars
+    pub jars: Vec<Pubkey>,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl OwnerRegistry {
+    // Maximum number of open jars listed per owner
+    pub const MAX_JARS: usize = 32;
+
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // owner (Pubkey)
+    4 + // next_index
+    4 + (Self::MAX_JARS * 32) + // Vec prefix(4) + jar addresses
+    1; // bump
+}
+
+/// Program-wide settings for a hosted deployment, derived from `[b"config"]`
+#[account]
+pub struct ProgramConfig {
+    /// the key allowed to change these settings
+    pub admin: Pubkey,
+    /// protocol fee skimmed from every SOL tip, in basis points
+    pub fee_bps: u16,
+    /// wallet that receives protocol fees
+    pub treasury: Pubkey,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl ProgramConfig {
+    /// Highest protocol fee the admin can set, 10%
+    pub const MAX_FEE_BPS: u16 = 1_000;
+
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // admin (Pubkey)
+    2 + // fee_bps
+    32 + // treasury (Pubkey)
+    1; // bump
+
+    /// Protocol fee owed on a tip of `amount` lamports, rounded down
+    pub fn fee_for(&self, amount: u64) -> u64 {
+        (amount as u128 * self.fee_bps as u128 / SplitShare::TOTAL_BPS as u128) as u64
+    }
+}
+
+/// Owner-configurable limits on SOL withdrawals, in lamports. Token withdrawals
+/// aren't counted against them; only the timelock applies to tokens.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct WithdrawalPolicy {
+    /// maximum lamports in a single withdrawal
+    pub per_tx_limit: u64,
+    /// maximum lamports withdrawn within one window
+    pub window_limit: u64,
+    /// window length in seconds, e.g. 86,400 for daily or 604,800 for weekly caps
+    pub window_duration: i64,
+    /// seconds a requested withdrawal waits before it can execute, zero allows direct withdrawals
+    pub timelock_duration: i64,
+}
+
+impl WithdrawalPolicy {
+    /// size of the policy in bytes
+    pub const SIZE: usize = 8 + // per_tx_limit
+    8 + // window_limit
+    8 + // window_duration
+    8; // timelock_duration
+
+    /// Seconds a looser policy waits before it takes effect
+    pub const LOOSENING_DELAY: i64 = 24 * 60 * 60;
+
+    /// Longest withdrawal window, one year
+    pub const MAX_WINDOW_DURATION: i64 = 365 * 24 * 60 * 60;
+
+    /// Longest timelock, one year, which keeps unlock times far from overflowing
+    pub const MAX_TIMELOCK_DURATION: i64 = 365 * 24 * 60 * 60;
+
+    /// True if the window and timelock are within their bounds
+    pub fn is_valid(&self) -> bool {
+        (1..=Self::MAX_WINDOW_DURATION).contains(&self.window_duration)
+            && (0..=Self::MAX_TIMELOCK_DURATION).contains(&self.timelock_duration)
+    }
+
+    /// True if `other` allows more to be withdrawn than `self` in any respect
+    pub fn is_looser(&self, other: &WithdrawalPolicy) -> bool {
+        other.per_tx_limit > self.per_tx_limit
+            || other.window_limit > self.window_limit
+            || other.window_duration < self.window_duration
+            || other.timelock_duration < self.timelock_duration
+    }
+}
+
+impl Default for WithdrawalPolicy {
+    /// No limits, with a daily window ready for when the owner sets a cap
+    fn default() -> Self {
+        Self {
+            per_tx_limit: u64::MAX,
+            window_limit: u64::MAX,
+            window_duration: 24 * 60 * 60,
+            timelock_duration: 0,
+        }
+    }
+}
+
+/// Rolling record of SOL withdrawals, kept as per-bucket totals over the last
+/// `BUCKETS` buckets. Buckets are sized so the tracked span always covers the
+/// whole window, so the limit holds over any `window_duration` seconds; a
+/// withdrawal may be counted for up to one bucket longer than the window.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct WithdrawalWindow {
+    /// lamports withdrawn per bucket, indexed by bucket number modulo `BUCKETS`
+    pub buckets: [u64; 8],
+    /// number of the latest bucket written to, `timestamp / bucket_len`
+    pub latest: i64,
+    /// bucket length in seconds the amounts were recorded with
+    pub bucket_len: i64,
+}
+
+impl WithdrawalWindow {
+    /// number of buckets tracked
+    pub const BUCKETS: usize = 8;
+
+    /// size of the window in bytes
+    pub const SIZE: usize = 8 * Self::BUCKETS + // buckets
+    8 + // latest
+    8; // bucket_len
+
+    /// Bucket length for a window, so that `BUCKETS - 1` full buckets cover it
+    fn bucket_len_for(window_duration: i64) -> i64 {
+        let spans = Self::BUCKETS as i64 - 1;
+        // Rounds up without adding to `window_duration`, which could overflow
+        (window_duration / spans + (window_duration % spans != 0) as i64).max(1)
+    }
+
+    fn slot(bucket: i64) -> usize {
+        bucket.rem_euclid(Self::BUCKETS as i64) as usize
+    }
+
+    fn total(&self) -> u64 {
+        self.buckets.iter().fold(0, |total, amount| total.saturating_add(*amount))
+    }
+
+    /// Lamports withdrawn within the window ending at `now`
+    pub fn withdrawn(&self, window_duration: i64, now: i64) -> u64 {
+        let bucket_len = Self::bucket_len_for(window_duration);
+        // Amounts recorded under another window length all count as recent
+        if bucket_len != self.bucket_len {
+            return self.total();
+        }
+
+        let oldest = (now / bucket_len - (Self::BUCKETS as i64 - 1))
+            .max(self.latest - (Self::BUCKETS as i64 - 1));
+        (oldest..=self.latest)
+            .map(|bucket| self.buckets[Self::slot(bucket)])
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Counts a withdrawal of `amount` lamports at `now`
+    pub fn record(&mut self, window_duration: i64, now: i64, amount: u64) {
+        let bucket_len = Self::bucket_len_for(window_duration);
+        let mut current = now / bucket_len;
+
+        if bucket_len != self.bucket_len {
+            // Carry everything into the current bucket, so changing the window
+            // length can't free up headroom early
+            let total = self.total();
+            self.buckets = [0; Self::BUCKETS];
+            self.buckets[Self::slot(current)] = total;
+            self.bucket_len = bucket_len;
+        } else {
+            // Clear the buckets that fell out of the window since the last write
+            current = current.max(self.latest);
+            let first = (self.latest + 1).max(current - (Self::BUCKETS as i64 - 1));
+            for bucket in first..=current {
+                self.buckets[Self::slot(bucket)] = 0;
+            }
+        }
+
+        self.latest = current;
+        let slot = Self::slot(current);
+        self.buckets[slot] = self.buckets[slot].saturating_add(amount);
+    }
+}
+
+/// A looser withdrawal policy queued behind `WithdrawalPolicy::LOOSENING_DELAY`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct PendingWithdrawalPolicy {
+    /// the policy that will replace the current one
+    pub policy: WithdrawalPolicy,
+    /// unix timestamp from which the policy applies
+    pub effective_at: i64,
+}
+
+impl PendingWithdrawalPolicy {
+    /// size of the pending policy in bytes
+    pub const SIZE: usize = WithdrawalPolicy::SIZE + // policy
+    8; // effective_at
+}
+
+/// A withdrawal waiting out the jar's timelock, derived from
+/// `[b"withdrawal", tipjar, id]`. Anyone can execute it once `unlock_at` has
+/// passed; the owner can cancel it before then.
+#[account]
+pub struct PendingWithdrawal {
+    /// the tip jar the lamports come from
+    pub tipjar: Pubkey,
+    /// sequential id of this request within the jar
+    pub id: u32,
+    /// lamports to withdraw
+    pub amount: u64,
+    /// account that will receive the lamports
+    pub destination: Pubkey,
+    /// owner or treasurer who made the request and paid its rent
+    pub requester: Pubkey,
+    /// whether `amount` was drawn from the requester's treasurer cap
+    pub charged_cap: bool,
+    /// unix timestamp when the request was made
+    pub requested_at: i64,
+    /// unix timestamp from which the withdrawal can execute
+    pub unlock_at: i64,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl PendingWithdrawal {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    4 + // id
+    8 + // amount
+    32 + // destination (Pubkey)
+    32 + // requester (Pubkey)
+    1 + // charged_cap
+    8 + // requested_at
+    8 + // unlock_at
+    1; // bump
+
+    /// True once the timelock has passed
+    pub fn is_unlocked(&self, now: i64) -> bool {
+        now >= self.unlock_at
+    }
+}
+
+/// A multisig proposal, derived from `[b"proposal", tipjar, id]`
+/// Executes once `threshold` current members of the owner set have approved it
+#[account]
+pub struct Proposal {
+    /// the tip jar the proposal acts on
+    pub tipjar: Pubkey,
+    /// sequential id of this proposal within the jar
+    pub id: u32,
+    /// member who created the proposal and paid its rent
+    pub proposer: Pubkey,
+    /// what happens when the proposal executes
+    pub action: ProposalAction,
+    /// members who approved, the proposer included
+    pub approvals: Vec<Pubkey>,
+    /// unix timestamp when the proposal was created
+    pub created_at: i64,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl Proposal {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    4 + // id
+    32 + // proposer (Pubkey)
+    ProposalAction::MAX_SIZE + // action
+    4 + (TipJar::MAX_SIGNERS * 32) + // approvals, Vec prefix(4) + members
+    8 + // created_at
+    1; // bump
+
+    /// Number of approvals from keys that are still in the owner set
+    pub fn approval_count(&self, tip_jar: &TipJar) -> usize {
+        self.approvals.iter().filter(|key| tip_jar.is_signer(key)).count()
+    }
+
+    /// Adds a current member's approval. Approvals from keys that left the owner
+    /// set are dropped first, so the list never outgrows `TipJar::MAX_SIGNERS`.
+    pub fn approve(&mut self, tip_jar: &TipJar, approver: Pubkey) -> Result<()> {
+        require!(!self.approvals.contains(&approver), TipJarError::AlreadyApproved);
+        self.approvals.retain(|key| tip_jar.is_signer(key));
+        self.approvals.push(approver);
+
+        Ok(())
+    }
+}
+
+/// Owner actions that a multisig jar can only take through a proposal
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum ProposalAction {
+    /// Pay lamports from the jar to `destination`
+    Withdraw { amount: u64, destination: Pubkey },
+    /// Pay tokens from the jar's vault for `mint` to the token account `destination`
+    WithdrawToken { mint: Pubkey, amount: u64, destination: Pubkey },
+    /// Replace the jar's description, category and goal
+    Update { description: String, category: String, goal: u64 },
+    /// Replace the owner set and threshold
+    SetOwnerSet { signers: Vec<Pubkey>, threshold: u8 },
+    /// Close the jar, sending its balance and token vaults to `destination`
+    Close { destination: Pubkey },
+    /// Replace the split table and choose whether tips are split as they arrive
+    SetSplits { splits: Vec<SplitShare>, split_on_receive: bool },
+    /// Replace the milestone plan
+    SetMilestones { milestones: Vec<MilestoneTarget> },
+    /// Mark the milestone at `index` delivered and release its funds
+    ReleaseMilestone { index: u8 },
+    /// Replace the SOL withdrawal limits and timelock, looser settings are queued
+    SetWithdrawalPolicy { policy: WithdrawalPolicy },
+    /// Set or clear the goal deadline, optionally as an all-or-nothing campaign
+    SetGoalDeadline { deadline: Option<i64>, all_or_nothing: bool },
+    /// Grant or update scoped roles for `delegate`
+    SetDelegate { delegate: Pubkey, roles: u8, withdraw_cap: u64 },
+    /// Replace the tip bounds and the history threshold
+    SetTipLimits { min_tip: u64, max_tip: Option<u64>, history_threshold: u64 },
+    /// Remove every role from `delegate`, its rent goes to the proposer
+    RevokeDelegate { delegate: Pubkey },
+    /// End a successful all-or-nothing campaign
+    ClaimCrowdfunding,
+    /// Abort the pending withdrawal with the given id, its rent goes back to the requester
+    CancelWithdrawal { id: u32 },
+    /// Switch between the inline ring buffer and per-tip records
+    SetInlineHistory { enabled: bool },
+    /// Make the jar private or public
+    SetPrivacy { is_private: bool },
+    /// Allow `member` to tip the jar while it is private
+    AddToAllowlist { member: Pubkey },
+    /// Remove `member` from the allowlist, the entry's rent goes to the proposer
+    RemoveFromAllowlist { member: Pubkey },
+    /// Set or clear the Merkle root of additional allowlisted senders
+    SetAllowlistRoot { root: Option<[u8; 32]> },
+    /// Stop tracking `mint`, so its vault is no longer swept on close
+    ForgetTokenMint { mint: Pubkey },
+}
+
+impl ProposalAction {
+    // the three largest variants
+    const SET_OWNER_SET_SIZE: usize = 4 + (TipJar::MAX_SIGNERS * 32) + 1; // signers + threshold
+    const UPDATE_SIZE: usize = 4 + TipJar::MAX_DESCRIPTION_LEN + 4 + TipJar::MAX_CATEGORY_LEN + 8; // description + category + goal
+    const SET_SPLITS_SIZE: usize = 4 + (TipJar::MAX_SPLITS * SplitShare::SIZE) + 1; // splits + split_on_receive
+
+    /// size of the largest variant plus the enum tag
+    pub const MAX_SIZE: usize = 1 + Self::larger(
+        Self::larger(Self::SET_OWNER_SET_SIZE, Self::UPDATE_SIZE),
+        Self::SET_SPLITS_SIZE,
+    );
+
+    const fn larger(a: usize, b: usize) -> usize {
+        if a > b { a } else { b }
+    }
+
+    /// Key whose `Delegate` account the action writes or closes, the default key otherwise
+    pub fn delegate_key(&self) -> Pubkey {
+        match self {
+            ProposalAction::SetDelegate { delegate, .. } | ProposalAction::RevokeDelegate { delegate } => *delegate,
+            _ => Pubkey::default(),
+        }
+    }
+
+    /// Member whose `AllowlistEntry` the action writes or closes, the default key otherwise
+    pub fn allowlist_member(&self) -> Pubkey {
+        match self {
+            ProposalAction::AddToAllowlist { member } | ProposalAction::RemoveFromAllowlist { member } => *member,
+            _ => Pubkey::default(),
+        }
+    }
 }
 
-// Represents a single tip with sender, amount and message
+/// Scoped management rights granted by the owner to another key, derived
+/// from `[b"delegate", tipjar, delegate]`
+#[account]
+pub struct Delegate {
+    /// the tip jar the roles apply to
+    pub tipjar: Pubkey,
+    /// the key holding the roles
+    pub delegate: Pubkey,
+    /// owner who granted the roles, they lapse if the jar changes hands
+    pub granted_by: Pubkey,
+    /// bitmask of `Delegate::MODERATOR`, `Delegate::EDITOR` and `Delegate::TREASURER`
+    pub roles: u8,
+    /// total lamports a treasurer may withdraw
+    pub withdraw_cap: u64,
+    /// lamports withdrawn so far under this delegation
+    pub withdrawn: u64,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl Delegate {
+    /// May pause, resume and clear tip history, and choose the inactive tip policy
+    pub const MODERATOR: u8 = 1 << 0;
+    /// May update the jar's description, category and goal
+    pub const EDITOR: u8 = 1 << 1;
+    /// May withdraw SOL up to `withdraw_cap`
+    pub const TREASURER: u8 = 1 << 2;
+    /// Every known role
+    pub const ALL_ROLES: u8 = Self::MODERATOR | Self::EDITOR | Self::TREASURER;
+
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    32 + // delegate (Pubkey)
+    32 + // granted_by (Pubkey)
+    1 + // roles
+    8 + // withdraw_cap
+    8 + // withdrawn
+    1; // bump
+
+    pub fn has_role(&self, role: u8) -> bool {
+        self.roles & role == role
+    }
+
+    /// Counts a withdrawal against the treasurer cap
+    pub fn record_withdrawal(&mut self, amount: u64) -> Result<()> {
+        let withdrawn = self.withdrawn.saturating_add(amount);
+        require!(withdrawn <= self.withdraw_cap, TipJarError::DelegateCapExceeded);
+        self.withdrawn = withdrawn;
+
+        Ok(())
+    }
+
+    /// Gives back cap spent on a withdrawal that was cancelled
+    pub fn restore_withdrawal(&mut self, amount: u64) {
+        self.withdrawn = self.withdrawn.saturating_sub(amount);
+    }
+}
+
+/// A single tip stored in its own PDA, derived from
+/// `[b"tip", tipjar, sequence]` where `sequence` is the jar's `next_sequence`
+/// at the time of the tip. History is unbounded and never overwritten.
+#[account]
+pub struct TipRecord {
+    /// the tip jar that received this tip
+    pub tipjar: Pubkey,
+    /// position of this tip in the jar's history, starting at zero
+    pub sequence: u32,
+    /// the tip itself
+    pub tip: Tip,
+    /// wallet that paid the record's rent, refunded when the record is cleared;
+    /// the default pubkey for anonymous tips
+    pub payer: Pubkey,
+    /// PDA bump used to derive this account's address
+    pub bump: u8,
+}
+
+impl TipRecord {
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + // account discriminator
+    32 + // tipjar (Pubkey)
+    4 + // sequence
+    Tip::SIZE + // tip
+    32 + // payer (Pubkey)
+    1; // bump
+
+    /// Fills a new record. Anonymous tips keep the payer hidden as well as the
+    /// sender, since the payer is usually the sender.
+    pub fn fill(&mut self, tipjar: Pubkey, sequence: u32, tip: Tip, payer: Pubkey, bump: u8) {
+        self.tipjar = tipjar;
+        self.sequence = sequence;
+        self.payer = tip.visibility.shown_sender(payer);
+        self.tip = tip;
+        self.bump = bump;
+    }
+}
+
+/// Fixed-capacity tip history kept in a zero-copy account derived from
+/// `[b"history", tipjar]`. Tips are appended in O(1) without deserializing
+/// the buffer; once full, the oldest tip is overwritten.
+#[account(zero_copy)]
+pub struct TipHistory {
+    /// the tip jar this history belongs to
+    pub tipjar: Pubkey,
+    /// slot index of the oldest tip in the buffer
+    pub head: u32,
+    /// number of occupied slots
+    pub len: u32,
+    /// ring buffer storage
+    pub slots: [TipSlot; TipHistory::CAPACITY],
+}
+
+impl TipHistory {
+    /// Number of tips kept in the ring buffer, sized to stay under the 10 KB account creation limit
+    pub const CAPACITY: usize = 50;
+
+    // total length constant used in account initialization
+    pub const LEN: usize = 8 + std::mem::size_of::<TipHistory>();
+
+    /// Appends a tip, overwriting the oldest one when the buffer is full
+    pub fn push(&mut self, sequence: u32, tip: &Tip) {
+        let capacity = Self::CAPACITY as u32;
+        if self.len < capacity {
+            let index = (self.head + self.len) % capacity;
+            self.slots[index as usize] = TipSlot::new(sequence, tip);
+            self.len += 1;
+        } else {
+            self.slots[self.head as usize] = TipSlot::new(sequence, tip);
+            self.head = (self.head + 1) % capacity;
+        }
+    }
+
+    /// Drops every tip from the buffer
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Blanks the memo of the tip with the given sequence, false if it is no longer buffered
+    pub fn redact(&mut self, sequence: u32) -> bool {
+        for position in 0..self.len {
+            let index = ((self.head + position) % Self::CAPACITY as u32) as usize;
+            let slot = &mut self.slots[index];
+            if slot.sequence == sequence {
+                slot.memo = [0; 100];
+                slot.memo_len = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the slot holding the `position`-th oldest tip in the buffer
+    fn slot_at(&self, position: u32) -> &TipSlot {
+        &self.slots[((self.head + position) % Self::CAPACITY as u32) as usize]
+    }
+
+    /// Reads up to `limit` tips in chronological order across the wraparound,
+    /// starting at tip sequence `cursor` (inclusive) or at the oldest/newest tip
+    pub fn page(&self, order: HistoryOrder, cursor: Option<u32>, limit: usize) -> TipHistoryPage {
+        let mut tips = Vec::with_capacity(limit);
+        let mut next_cursor = None;
+        for step in 0..self.len {
+            let position = match order {
+                HistoryOrder::OldestFirst => step,
+                HistoryOrder::NewestFirst => self.len - 1 - step,
+            };
+            let slot = self.slot_at(position);
+
+            // Skip tips on the far side of the cursor
+            let before_cursor = match (order, cursor) {
+                (HistoryOrder::OldestFirst, Some(cursor)) => slot.sequence < cursor,
+                (HistoryOrder::NewestFirst, Some(cursor)) => slot.sequence > cursor,
+                (_, None) => false,
+            };
+            if before_cursor {
+                continue;
+            }
+
+            if tips.len() == limit {
+                next_cursor = Some(slot.sequence);
+                break;
+            }
+            tips.push(TipHistoryEntry {
+                sequence: slot.sequence,
+                tip: slot.to_tip(),
+            });
+        }
+
+        TipHistoryPage { tips, next_cursor }
+    }
+}
+
+/// Fixed-size tip layout used inside `TipHistory`
+#[zero_copy]
+pub struct TipSlot {
+    /// public key of the tip sender, zeroed for anonymous tips
+    pub sender: Pubkey,
+    /// token mint of the tip, only meaningful when `has_mint` is set
+    pub mint: Pubkey,
+    /// amount sent, in lamports for SOL tips or base units for token tips
+    pub amount: u64,
+    /// unix timestamp when the tip was sent
+    pub timestamp: u64,
+    /// position of the tip in the jar's history
+    pub sequence: u32,
+    /// memo bytes, only the first `memo_len` are used
+    pub memo: [u8; 100],
+    /// length of the memo in bytes
+    pub memo_len: u8,
+    /// `Visibility` discriminant
+    pub visibility: u8,
+    /// 1 for token tips, 0 for SOL tips
+    pub has_mint: u8,
+    /// keeps the slot 8-byte aligned
+    pub _padding: [u8; 5],
+}
+
+impl TipSlot {
+    /// Packs a tip into its fixed-size layout
+    pub fn new(sequence: u32, tip: &Tip) -> Self {
+        let mut memo = [0u8; 100];
+        let memo_len = tip.memo.len().min(memo.len());
+        memo[..memo_len].copy_from_slice(&tip.memo.as_bytes()[..memo_len]);
+
+        Self {
+            sender: tip.sender,
+            mint: tip.mint.unwrap_or_default(),
+            amount: tip.amount,
+            timestamp: tip.timestamp,
+            sequence,
+            memo,
+            memo_len: memo_len as u8,
+            visibility: tip.visibility as u8,
+            has_mint: tip.mint.is_some() as u8,
+            _padding: [0; 5],
+        }
+    }
+
+    /// Unpacks the slot back into a regular tip
+    pub fn to_tip(&self) -> Tip {
+        Tip {
+            sender: self.sender,
+            amount: self.amount,
+            mint: if self.has_mint == 1 { Some(self.mint) } else { None },
+            visibility: if self.visibility == Visibility::Anonymous as u8 {
+                Visibility::Anonymous
+            } else {
+                Visibility::Public
+            },
+            memo: String::from_utf8_lossy(&self.memo[..self.memo_len as usize]).into_owned(),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+/// How a jar handles SOL tips that arrive while it is inactive
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InactiveTipPolicy {
+    /// Fail the transaction with `InactiveTipJar`
+    #[default]
+    Reject,
+    /// Hold the tip in a `RefundEscrow` the sender can claim back
+    Escrow,
+}
+
+/// Order in which `get_tip_history` walks the history
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryOrder {
+    /// Start from the earliest tip and move forward in time
+    OldestFirst,
+    /// Start from the latest tip and move back in time
+    NewestFirst,
+}
+
+/// Where a jar keeps its tip history
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryMode {
+    /// Every tip gets its own `TipRecord` PDA (unbounded history)
+    #[default]
+    Records,
+    /// Tips go into the jar's zero-copy `TipHistory` ring buffer
+    Inline,
+}
+
+/// A tip together with its position in the jar's history
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TipHistoryEntry {
+    /// position of the tip in the jar's history
+    pub sequence: u32,
+    /// the tip itself
+    pub tip: Tip,
+}
+
+/// One page of tip history returned by `get_tip_history`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TipHistoryPage {
+    /// tips in this page, in the requested order
+    pub tips: Vec<TipHistoryEntry>,
+    /// sequence to pass as `cursor` for the next page, `None` when there are no more tips
+    pub next_cursor: Option<u32>,
+}
+
+// Running total of tips received in a single SPL token mint
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenTotal {
+   /// mint of the token
+    pub mint: Pubkey,
+   /// total amount received in the mint's base units
+    pub total_received: u64,
+}
+
+impl TokenTotal {
+   /// size of a single token total in bytes
+    pub const SIZE: usize = 32 + // mint (Pubkey)
+    8; // total_received
+}
+
+// A staged funding target; its funds unlock once reached and marked delivered
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Milestone {
+   /// short description of what the milestone delivers
+    pub label: String,
+   /// cumulative lamports received at which the milestone is reached
+    pub target: u64,
+   /// whether `total_received` has reached the target
+    pub reached: bool,
+   /// whether the owner marked it delivered, unlocking withdrawals up to the target
+    pub released: bool,
+}
+
+impl Milestone {
+   /// maximum label length in bytes
+    pub const MAX_LABEL_LEN: usize = 32;
+
+   /// size of a single milestone in bytes
+    pub const SIZE: usize = 4 + Self::MAX_LABEL_LEN + // label, String prefix(4) + max chars
+    8 + // target
+    1 + // reached
+    1; // released
+}
+
+// A milestone as given by the owner when setting up the plan
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MilestoneTarget {
+   /// short description of what the milestone delivers
+    pub label: String,
+   /// cumulative lamports received at which the milestone is reached
+    pub target: u64,
+}
+
+// One beneficiary's share of the jar's revenue
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SplitShare {
+   /// wallet that receives this share
+    pub recipient: Pubkey,
+   /// share of each distribution in basis points, all shares add up to 10,000
+    pub bps: u16,
+}
+
+impl SplitShare {
+   /// basis points in a whole distribution
+    pub const TOTAL_BPS: u16 = 10_000;
+
+   /// size of a single split share in bytes
+    pub const SIZE: usize = 32 + // recipient (Pubkey)
+    2; // bps
+}
+
+// Represents a single tip with sender, amount and message
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
 pub struct Tip {
-   /// public key of the tip sender
+   /// public key of the tip sender, zeroed for anonymous tips
     pub sender: Pubkey,
-   /// amount of SOL sent in lamports
+   /// amount sent, in lamports for SOL tips or base units for token tips
     pub amount: u64,
+   /// token mint of the tip, `None` for SOL tips
+    pub mint: Option<Pubkey>,
    /// whether this tip is publicly visible or anonymous
     pub visibility: Visibility,
    /// optional message included with the tip
@@ -83,16 +1594,754 @@ impl Tip {
    /// size of a single tip in bytes
     pub const SIZE: usize = 32 + // sender (Pubkey)
     8 + // amount
+    (1 + 32) + // mint (Option<Pubkey>)
     1 + // visibility (enum)
     (4 + 100) + // memo length (u32)
-    8; // timestamp (u64) 
+    8; // timestamp (u64)
 }
 
 /// Enum for tip visibility
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Visibility {
     /// Tip is publicly visible with sender info
+    #[default]
     Public,
     /// Tip is anonymous and only amount is visible
     Anonymous,
-}
\ No newline at end of file
+}
+
+impl Visibility {
+    /// Sender as stored in history and events, the default pubkey for anonymous tips
+    pub fn shown_sender(self, sender: Pubkey) -> Pubkey {
+        match self {
+            Visibility::Public => sender,
+            Visibility::Anonymous => Pubkey::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn withdrawal_window_counts_withdrawals_for_the_whole_window() {
+        let mut window = WithdrawalWindow::default();
+        window.record(DAY, 10 * DAY, 100);
+
+        for elapsed in [0, 1, DAY / 2, DAY - 1] {
+            assert_eq!(window.withdrawn(DAY, 10 * DAY + elapsed), 100);
+        }
+        assert_eq!(window.withdrawn(DAY, 12 * DAY), 0);
+    }
+
+    #[test]
+    fn withdrawal_window_does_not_reset_at_a_boundary() {
+        // A full cap just before a fixed window would end still counts right after it
+        let mut window = WithdrawalWindow::default();
+        window.record(DAY, DAY - 1, 100);
+
+        assert_eq!(window.withdrawn(DAY, DAY + 1), 100);
+        assert_eq!(window.withdrawn(DAY, 2 * DAY - 2), 100);
+    }
+
+    #[test]
+    fn withdrawal_window_drops_expired_buckets_on_record() {
+        let mut window = WithdrawalWindow::default();
+        window.record(DAY, 0, 100);
+        window.record(DAY, DAY / 2, 50);
+        assert_eq!(window.withdrawn(DAY, DAY / 2), 150);
+
+        // The first withdrawal has left the window, the second has not
+        window.record(DAY, DAY + DAY / 4, 10);
+        assert_eq!(window.withdrawn(DAY, DAY + DAY / 4), 60);
+
+        // Long after, only the new withdrawal counts
+        window.record(DAY, 30 * DAY, 5);
+        assert_eq!(window.withdrawn(DAY, 30 * DAY), 5);
+    }
+
+    #[test]
+    fn withdrawal_window_handles_huge_windows() {
+        let mut window = WithdrawalWindow::default();
+        window.record(i64::MAX, DAY, 100);
+        assert_eq!(window.withdrawn(i64::MAX, DAY), 100);
+    }
+
+    #[test]
+    fn withdrawal_policy_bounds_the_window() {
+        let mut policy = WithdrawalPolicy::default();
+        assert!(policy.is_valid());
+
+        policy.window_duration = 0;
+        assert!(!policy.is_valid());
+        policy.window_duration = WithdrawalPolicy::MAX_WINDOW_DURATION + 1;
+        assert!(!policy.is_valid());
+        policy.window_duration = WithdrawalPolicy::MAX_WINDOW_DURATION;
+        assert!(policy.is_valid());
+    }
+
+    #[test]
+    fn withdrawal_policy_bounds_the_timelock() {
+        let mut policy = WithdrawalPolicy { timelock_duration: -1, ..Default::default() };
+        assert!(!policy.is_valid());
+        policy.timelock_duration = i64::MAX;
+        assert!(!policy.is_valid());
+        policy.timelock_duration = WithdrawalPolicy::MAX_TIMELOCK_DURATION;
+        assert!(policy.is_valid());
+    }
+
+    #[test]
+    fn withdrawal_policy_is_looser_in_any_respect() {
+        let current = WithdrawalPolicy {
+            per_tx_limit: 100,
+            window_limit: 1_000,
+            window_duration: DAY,
+            timelock_duration: DAY,
+        };
+        assert!(!current.is_looser(&current));
+
+        // Tightening every field, or any one of them, never counts as looser
+        let tighter = WithdrawalPolicy {
+            per_tx_limit: 50,
+            window_limit: 500,
+            window_duration: 7 * DAY,
+            timelock_duration: 2 * DAY,
+        };
+        assert!(!current.is_looser(&tighter));
+        assert!(!current.is_looser(&WithdrawalPolicy { per_tx_limit: 50, ..current }));
+
+        assert!(current.is_looser(&WithdrawalPolicy { per_tx_limit: 101, ..current }));
+        assert!(current.is_looser(&WithdrawalPolicy { window_limit: 1_001, ..current }));
+        assert!(current.is_looser(&WithdrawalPolicy { window_duration: DAY - 1, ..current }));
+        assert!(current.is_looser(&WithdrawalPolicy { timelock_duration: 0, ..current }));
+
+        // Loosening one field counts even when the others are tightened
+        assert!(current.is_looser(&WithdrawalPolicy { window_limit: u64::MAX, ..tighter }));
+    }
+
+    #[test]
+    fn withdrawal_window_keeps_amounts_when_the_window_changes() {
+        let mut window = WithdrawalWindow::default();
+        window.record(DAY, DAY, 100);
+
+        assert_eq!(window.withdrawn(7 * DAY, DAY), 100);
+        window.record(7 * DAY, DAY, 10);
+        assert_eq!(window.withdrawn(7 * DAY, DAY), 110);
+        assert_eq!(window.withdrawn(7 * DAY, 9 * DAY), 0);
+    }
+
+    /// A jar with every field zeroed, as if freshly allocated
+    fn blank_tip_jar() -> TipJar {
+        TipJar::deserialize(&mut &[0u8; TipJar::LEN][..]).unwrap()
+    }
+
+    fn milestone(target: u64) -> MilestoneTarget {
+        MilestoneTarget { label: format!("{target} lamports"), target }
+    }
+
+    #[test]
+    fn release_headroom_is_capped_by_the_last_released_milestone() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.set_milestones(vec![milestone(10), milestone(20)]).unwrap();
+        assert_eq!(tip_jar.release_headroom(), 0);
+
+        tip_jar.total_received = 15;
+        tip_jar.milestones[0].reached = true;
+        tip_jar.milestones[0].released = true;
+        tip_jar.total_withdrawn = 4;
+        assert_eq!(tip_jar.release_headroom(), 6);
+
+        // The plan is partly released, so it can't be rewritten
+        assert!(tip_jar.set_milestones(vec![]).is_err());
+    }
+
+    #[test]
+    fn release_headroom_is_unlimited_once_the_plan_is_complete() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.set_milestones(vec![milestone(10)]).unwrap();
+
+        // Tips above the final target must not stay locked in the jar
+        tip_jar.total_received = 15;
+        tip_jar.milestones[0].reached = true;
+        tip_jar.milestones[0].released = true;
+        tip_jar.total_withdrawn = 10;
+        assert_eq!(tip_jar.release_headroom(), u64::MAX);
+
+        // A completed plan can be cleared or replaced
+        tip_jar.set_milestones(vec![milestone(20), milestone(30)]).unwrap();
+        assert_eq!(tip_jar.goal, 30);
+        tip_jar.set_milestones(vec![]).unwrap();
+        assert_eq!(tip_jar.release_headroom(), u64::MAX);
+    }
+
+    #[test]
+    fn owner_key_alone_is_not_authorized_on_a_multisig_jar() {
+        let mut tip_jar = blank_tip_jar();
+        let owner = Pubkey::new_unique();
+        tip_jar.owner = owner;
+        assert!(tip_jar.is_authorized(&owner, None, Delegate::MODERATOR));
+
+        tip_jar.set_owner_set(vec![owner, Pubkey::new_unique()], 2).unwrap();
+        assert!(!tip_jar.is_authorized(&owner, None, Delegate::MODERATOR));
+
+        // Delegates granted for the jar keep their roles
+        let delegate = Delegate {
+            tipjar: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            granted_by: owner,
+            roles: Delegate::MODERATOR,
+            withdraw_cap: 0,
+            withdrawn: 0,
+            bump: 0,
+        };
+        assert!(tip_jar.is_authorized(&delegate.delegate, Some(&delegate), Delegate::MODERATOR));
+        assert!(!tip_jar.is_authorized(&delegate.delegate, Some(&delegate), Delegate::EDITOR));
+    }
+
+    #[test]
+    fn proposal_approvals_fit_after_the_owner_set_rotates() {
+        let mut tip_jar = blank_tip_jar();
+        let old_set: Vec<Pubkey> = (0..TipJar::MAX_SIGNERS).map(|_| Pubkey::new_unique()).collect();
+        tip_jar.set_owner_set(old_set.clone(), 10).unwrap();
+
+        let mut proposal = Proposal {
+            tipjar: Pubkey::new_unique(),
+            id: 0,
+            proposer: old_set[0],
+            action: ProposalAction::ClaimCrowdfunding,
+            approvals: vec![old_set[0]],
+            created_at: 0,
+            bump: 0,
+        };
+        for signer in old_set[1..9].iter() {
+            proposal.approve(&tip_jar, *signer).unwrap();
+        }
+        assert!(proposal.approve(&tip_jar, old_set[1]).is_err());
+
+        // A whole new set approves, stale approvals make room for theirs
+        let mut new_set = old_set[..2].to_vec();
+        new_set.extend((2..TipJar::MAX_SIGNERS).map(|_| Pubkey::new_unique()));
+        tip_jar.set_owner_set(new_set.clone(), 10).unwrap();
+        for signer in new_set[2..].iter() {
+            proposal.approve(&tip_jar, *signer).unwrap();
+            assert!(proposal.approvals.len() <= TipJar::MAX_SIGNERS);
+        }
+        assert_eq!(proposal.approval_count(&tip_jar), TipJar::MAX_SIGNERS);
+        assert_eq!(proposal.approvals.len(), TipJar::MAX_SIGNERS);
+
+        // The full list still serializes into the space allocated for it
+        let mut data = vec![0u8; Proposal::LEN];
+        proposal.try_serialize(&mut &mut data[..]).unwrap();
+    }
+
+    #[test]
+    fn set_tip_limits_rejects_an_empty_range() {
+        let mut tip_jar = blank_tip_jar();
+        assert!(tip_jar.set_tip_limits(0, Some(0), 0).is_err());
+        assert!(tip_jar.set_tip_limits(10, Some(9), 0).is_err());
+
+        tip_jar.set_tip_limits(10, Some(10), 5).unwrap();
+        assert_eq!((tip_jar.min_tip, tip_jar.max_tip, tip_jar.history_threshold), (10, Some(10), 5));
+        tip_jar.set_tip_limits(10, None, 0).unwrap();
+        assert_eq!(tip_jar.max_tip, None);
+    }
+
+    #[test]
+    fn check_tip_amount_enforces_both_bounds_inclusively() {
+        let mut tip_jar = blank_tip_jar();
+        assert!(tip_jar.check_tip_amount(0).is_ok());
+
+        tip_jar.set_tip_limits(10, Some(20), 0).unwrap();
+        assert!(tip_jar.check_tip_amount(9).is_err());
+        assert!(tip_jar.check_tip_amount(10).is_ok());
+        assert!(tip_jar.check_tip_amount(20).is_ok());
+        assert!(tip_jar.check_tip_amount(21).is_err());
+
+        // Without a maximum any amount above the minimum is accepted
+        tip_jar.set_tip_limits(10, None, 0).unwrap();
+        assert!(tip_jar.check_tip_amount(u64::MAX).is_ok());
+    }
+
+    #[test]
+    fn record_token_amount_saturates_the_running_total() {
+        let mut tip_jar = blank_tip_jar();
+        let mint = Pubkey::new_unique();
+        tip_jar.record_token_amount(mint, u64::MAX - 1).unwrap();
+        tip_jar.record_token_amount(mint, 5).unwrap();
+
+        assert_eq!(tip_jar.token_totals.len(), 1);
+        assert_eq!(tip_jar.token_totals[0].total_received, u64::MAX);
+    }
+
+    #[test]
+    fn forget_token_mint_frees_a_slot() {
+        let mut tip_jar = blank_tip_jar();
+        let mints: Vec<Pubkey> = (0..TipJar::MAX_TOKEN_MINTS).map(|_| Pubkey::new_unique()).collect();
+        for mint in mints.iter() {
+            tip_jar.record_token_amount(*mint, 1).unwrap();
+        }
+        let new_mint = Pubkey::new_unique();
+        assert!(tip_jar.record_token_amount(new_mint, 1).is_err());
+
+        tip_jar.forget_token_mint(&mints[3]).unwrap();
+        assert!(tip_jar.forget_token_mint(&mints[3]).is_err());
+        tip_jar.record_token_amount(new_mint, 1).unwrap();
+
+        // The other mints keep their order, which the close sweep relies on
+        let tracked: Vec<Pubkey> = tip_jar.token_totals.iter().map(|total| total.mint).collect();
+        assert_eq!(tracked[..3], mints[..3]);
+        assert_eq!(tracked[3..9], mints[4..]);
+        assert_eq!(tracked[9], new_mint);
+    }
+
+    fn split(bps: u16) -> SplitShare {
+        SplitShare { recipient: Pubkey::new_unique(), bps }
+    }
+
+    #[test]
+    fn split_shares_gives_the_rounding_dust_to_the_first_recipient() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.set_splits(vec![split(3_333), split(3_333), split(3_334)]).unwrap();
+
+        assert_eq!(tip_jar.split_shares(100), vec![34, 33, 33]);
+        assert_eq!(tip_jar.split_shares(1), vec![1, 0, 0]);
+        assert_eq!(tip_jar.split_shares(u64::MAX).iter().sum::<u64>(), u64::MAX);
+    }
+
+    #[test]
+    fn set_splits_requires_shares_to_add_up_to_a_whole() {
+        let mut tip_jar = blank_tip_jar();
+        assert!(tip_jar.set_splits(vec![split(5_000), split(4_999)]).is_err());
+        assert!(tip_jar.set_splits(vec![split(5_000), split(5_001)]).is_err());
+        assert!(tip_jar.set_splits(vec![split(10_000), split(0)]).is_err());
+        assert!(tip_jar.set_splits(vec![split(1_000); TipJar::MAX_SPLITS + 1]).is_err());
+
+        let share = split(5_000);
+        assert!(tip_jar.set_splits(vec![share, share]).is_err());
+
+        tip_jar.set_splits(vec![split(2_500), split(7_500)]).unwrap();
+        tip_jar.set_split_on_receive(true).unwrap();
+
+        // Clearing the table also stops routing tips to it
+        tip_jar.set_splits(vec![]).unwrap();
+        assert!(!tip_jar.split_on_receive);
+    }
+
+    #[test]
+    fn fee_for_rounds_down_and_stays_under_the_cap() {
+        let config = ProgramConfig {
+            admin: Pubkey::new_unique(),
+            fee_bps: ProgramConfig::MAX_FEE_BPS,
+            treasury: Pubkey::new_unique(),
+            bump: 0,
+        };
+        assert_eq!(config.fee_for(1_000), 100);
+        assert_eq!(config.fee_for(9), 0);
+        assert_eq!(config.fee_for(u64::MAX), u64::MAX / 10);
+
+        let config = ProgramConfig { fee_bps: 0, ..config };
+        assert_eq!(config.fee_for(u64::MAX), 0);
+    }
+
+    /// A full ring buffer that has wrapped: it holds tips 10 through 59
+    fn wrapped_history() -> Box<TipHistory> {
+        let mut history: Box<TipHistory> = Box::new(bytemuck::Zeroable::zeroed());
+        for sequence in 0..60 {
+            let tip = Tip {
+                sender: Pubkey::new_unique(),
+                amount: sequence as u64,
+                mint: None,
+                visibility: Visibility::Public,
+                memo: format!("tip {sequence}"),
+                timestamp: sequence as u64,
+            };
+            history.push(sequence, &tip);
+        }
+        history
+    }
+
+    fn sequences(page: &TipHistoryPage) -> Vec<u32> {
+        page.tips.iter().map(|entry| entry.sequence).collect()
+    }
+
+    #[test]
+    fn tip_history_push_overwrites_the_oldest_tip() {
+        let history = wrapped_history();
+
+        assert_eq!(history.len as usize, TipHistory::CAPACITY);
+        assert_eq!(history.head, 10);
+        for position in 0..history.len {
+            let slot = history.slot_at(position);
+            assert_eq!(slot.sequence, position + 10);
+            assert_eq!(slot.to_tip().memo, format!("tip {}", position + 10));
+        }
+    }
+
+    #[test]
+    fn tip_history_pages_a_wrapped_buffer_without_a_cursor() {
+        let history = wrapped_history();
+
+        let page = history.page(HistoryOrder::OldestFirst, None, 20);
+        assert_eq!(sequences(&page), (10..30).collect::<Vec<_>>());
+        assert_eq!(page.next_cursor, Some(30));
+
+        let page = history.page(HistoryOrder::NewestFirst, None, 20);
+        assert_eq!(sequences(&page), (40..60).rev().collect::<Vec<_>>());
+        assert_eq!(page.next_cursor, Some(39));
+    }
+
+    #[test]
+    fn tip_history_pages_a_wrapped_buffer_from_a_cursor() {
+        let history = wrapped_history();
+
+        let page = history.page(HistoryOrder::OldestFirst, Some(45), 10);
+        assert_eq!(sequences(&page), (45..55).collect::<Vec<_>>());
+        assert_eq!(page.next_cursor, Some(55));
+
+        let page = history.page(HistoryOrder::NewestFirst, Some(45), 10);
+        assert_eq!(sequences(&page), (36..46).rev().collect::<Vec<_>>());
+        assert_eq!(page.next_cursor, Some(35));
+    }
+
+    #[test]
+    fn tip_history_next_cursor_at_the_edges() {
+        let history = wrapped_history();
+
+        // A page ending exactly on the last tip has no next page
+        let page = history.page(HistoryOrder::OldestFirst, Some(50), 10);
+        assert_eq!(sequences(&page), (50..60).collect::<Vec<_>>());
+        assert_eq!(page.next_cursor, None);
+        let page = history.page(HistoryOrder::NewestFirst, Some(19), 10);
+        assert_eq!(sequences(&page), (10..20).rev().collect::<Vec<_>>());
+        assert_eq!(page.next_cursor, None);
+
+        // One short of the end still points at the last tip
+        let page = history.page(HistoryOrder::OldestFirst, Some(50), 9);
+        assert_eq!(page.next_cursor, Some(59));
+        let page = history.page(HistoryOrder::NewestFirst, Some(19), 9);
+        assert_eq!(page.next_cursor, Some(10));
+
+        // The whole buffer in one page
+        let page = history.page(HistoryOrder::NewestFirst, None, TipHistory::CAPACITY);
+        assert_eq!(page.tips.len(), TipHistory::CAPACITY);
+        assert_eq!(page.next_cursor, None);
+
+        // Cursors that were overwritten start at the oldest tip, cursors past the newest return nothing
+        let page = history.page(HistoryOrder::OldestFirst, Some(3), 5);
+        assert_eq!(sequences(&page), (10..15).collect::<Vec<_>>());
+        let page = history.page(HistoryOrder::OldestFirst, Some(60), 5);
+        assert!(page.tips.is_empty());
+        assert_eq!(page.next_cursor, None);
+        let page = history.page(HistoryOrder::NewestFirst, Some(9), 5);
+        assert!(page.tips.is_empty());
+        assert_eq!(page.next_cursor, None);
+    }
+
+    /// A jar running campaign 1 for a 100 lamport goal that ends on day 10
+    fn campaign_jar() -> TipJar {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.goal = 100;
+        tip_jar.deadline = Some(10 * DAY);
+        tip_jar.crowdfunding = true;
+        tip_jar.campaign_id = 1;
+        tip_jar
+    }
+
+    fn blank_escrow() -> RefundEscrow {
+        RefundEscrow::deserialize(&mut &[0u8; RefundEscrow::LEN][..]).unwrap()
+    }
+
+    #[test]
+    fn pledges_are_locked_while_the_campaign_runs() {
+        let mut tip_jar = campaign_jar();
+        let mut escrow = blank_escrow();
+        tip_jar.record_pledge(&mut escrow, 40, Visibility::Anonymous);
+        assert_eq!(tip_jar.locked_lamports(), 40);
+        assert!(tip_jar.has_open_pledge(&escrow));
+
+        // Up to and including the deadline the pledge can be neither refunded nor settled
+        assert!(!tip_jar.campaign_failed(10 * DAY));
+        assert!(tip_jar.unwind_pledge(&mut escrow, None, 10 * DAY).is_err());
+        assert!(tip_jar.settle_pledge(&mut escrow, None, 10 * DAY).is_err());
+        assert_eq!(escrow.pledged, 40);
+        assert_eq!(escrow.amount, 0);
+        assert_eq!(tip_jar.locked_lamports(), 40);
+    }
+
+    #[test]
+    fn failed_campaign_refunds_every_pledge() {
+        let mut tip_jar = campaign_jar();
+        let (mut first, mut second) = (blank_escrow(), blank_escrow());
+        tip_jar.record_pledge(&mut first, 30, Visibility::Anonymous);
+        tip_jar.record_pledge(&mut second, 20, Visibility::Anonymous);
+        tip_jar.record_pledge(&mut first, 5, Visibility::Anonymous);
+        assert_eq!(first.pledged, 35);
+
+        let after = 10 * DAY + 1;
+        assert!(tip_jar.campaign_failed(after));
+        assert_eq!(tip_jar.unwind_pledge(&mut first, None, after).unwrap(), 35);
+        assert!(!tip_jar.has_open_pledge(&first));
+
+        // The campaign stays open until its last pledge is refunded
+        assert!(tip_jar.crowdfunding);
+        assert_eq!(tip_jar.locked_lamports(), 20);
+        assert_eq!(tip_jar.unwind_pledge(&mut second, None, after).unwrap(), 20);
+        assert!(!tip_jar.crowdfunding);
+        assert_eq!(tip_jar.locked_lamports(), 0);
+        assert_eq!(tip_jar.total_received, 0);
+    }
+
+    #[test]
+    fn settled_pledge_is_claimed_from_the_escrow() {
+        let mut tip_jar = campaign_jar();
+        let (mut settled, mut waiting) = (blank_escrow(), blank_escrow());
+        tip_jar.record_pledge(&mut settled, 30, Visibility::Anonymous);
+        tip_jar.record_pledge(&mut waiting, 20, Visibility::Anonymous);
+
+        let after = 10 * DAY + 1;
+        assert_eq!(tip_jar.settle_pledge(&mut settled, None, after).unwrap(), 30);
+        assert_eq!(settled.amount, 30);
+        assert_eq!(tip_jar.locked_lamports(), 20);
+
+        // The pledge now sits in the escrow, so claiming pays its balance instead of
+        // unwinding the pledge a second time
+        assert!(!tip_jar.has_open_pledge(&settled));
+        assert!(tip_jar.settle_pledge(&mut settled, None, after).is_err());
+        assert_eq!(settled.amount, 30);
+
+        // A contributor who never settled can still claim straight from the jar
+        assert!(tip_jar.has_open_pledge(&waiting));
+        assert_eq!(tip_jar.unwind_pledge(&mut waiting, None, after).unwrap(), 20);
+        assert!(!tip_jar.crowdfunding);
+    }
+
+    #[test]
+    fn claiming_a_funded_campaign_releases_the_lock() {
+        let mut tip_jar = campaign_jar();
+        let mut escrow = blank_escrow();
+        tip_jar.record_pledge(&mut escrow, 60, Visibility::Anonymous);
+
+        // Short of the goal the campaign can't be claimed
+        assert!(tip_jar.claim_campaign().is_err());
+
+        tip_jar.record_pledge(&mut escrow, 40, Visibility::Anonymous);
+        let after = 10 * DAY + 1;
+        assert!(!tip_jar.campaign_failed(after));
+        assert!(tip_jar.unwind_pledge(&mut escrow, None, after).is_err());
+
+        assert_eq!(tip_jar.claim_campaign().unwrap(), 100);
+        assert!(!tip_jar.crowdfunding);
+        assert_eq!(tip_jar.locked_lamports(), 0);
+        assert_eq!(tip_jar.total_received, 100);
+        assert!(!tip_jar.has_open_pledge(&escrow));
+        assert!(tip_jar.claim_campaign().is_err());
+    }
+
+    #[test]
+    fn pledges_from_an_earlier_campaign_are_not_carried_over() {
+        let mut tip_jar = campaign_jar();
+        let mut escrow = blank_escrow();
+        tip_jar.record_pledge(&mut escrow, 100, Visibility::Public);
+        tip_jar.claim_campaign().unwrap();
+
+        // The next campaign starts from scratch, as `set_goal_deadline` leaves it
+        tip_jar.crowdfunding = true;
+        tip_jar.campaign_id = 2;
+        tip_jar.pledged_total = 0;
+        tip_jar.deadline = Some(20 * DAY);
+        assert!(!tip_jar.has_open_pledge(&escrow));
+
+        tip_jar.record_pledge(&mut escrow, 10, Visibility::Anonymous);
+        assert_eq!(escrow.campaign_id, 2);
+        assert_eq!((escrow.pledged, escrow.credited), (10, 0));
+        assert_eq!(tip_jar.locked_lamports(), 10);
+    }
+
+    #[test]
+    fn held_tips_are_refundable_alongside_a_live_pledge() {
+        let mut tip_jar = campaign_jar();
+        let mut escrow = blank_escrow();
+        // Tips held while the jar was paused
+        escrow.amount = 25;
+        assert!(!tip_jar.has_open_pledge(&escrow));
+
+        tip_jar.record_pledge(&mut escrow, 40, Visibility::Anonymous);
+        assert!(tip_jar.has_open_pledge(&escrow));
+        assert_eq!(escrow.amount, 25);
+        assert_eq!(tip_jar.locked_lamports(), 40);
+
+        // Only the held tips come back, the pledge stays with the jar
+        assert_eq!(escrow.release_held_tips().unwrap(), 25);
+        assert_eq!(escrow.pledged, 40);
+        assert!(escrow.release_held_tips().is_err());
+
+        // Once the campaign fails, a settled pledge joins the held balance
+        escrow.amount = 5;
+        tip_jar.settle_pledge(&mut escrow, None, 10 * DAY + 1).unwrap();
+        assert_eq!(escrow.amount, 45);
+        assert!(!tip_jar.has_open_pledge(&escrow));
+    }
+
+    fn timelock_policy(timelock_duration: i64) -> WithdrawalPolicy {
+        WithdrawalPolicy { timelock_duration, ..Default::default() }
+    }
+
+    #[test]
+    fn requested_withdrawal_unlocks_after_the_timelock() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.withdrawal_policy = WithdrawalPolicy::default();
+        // A longer timelock is stricter, so it applies at once
+        assert_eq!(tip_jar.set_withdrawal_policy(timelock_policy(2 * DAY), DAY).unwrap(), DAY);
+        assert!(tip_jar.pending_policy.is_none());
+
+        let mut pending =
+            PendingWithdrawal::deserialize(&mut &[0u8; PendingWithdrawal::LEN][..]).unwrap();
+        pending.requested_at = DAY;
+        pending.unlock_at = tip_jar.withdrawal_unlock_at(DAY);
+        assert_eq!(pending.unlock_at, 3 * DAY);
+        assert!(!pending.is_unlocked(3 * DAY - 1));
+        assert!(pending.is_unlocked(3 * DAY));
+
+        assert!(tip_jar.set_withdrawal_policy(timelock_policy(-1), DAY).is_err());
+        assert!(tip_jar
+            .set_withdrawal_policy(timelock_policy(WithdrawalPolicy::MAX_TIMELOCK_DURATION + 1), DAY)
+            .is_err());
+    }
+
+    #[test]
+    fn shorter_timelock_waits_out_the_loosening_delay() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.withdrawal_policy = timelock_policy(2 * DAY);
+
+        let effective_at = tip_jar.set_withdrawal_policy(timelock_policy(0), DAY).unwrap();
+        assert_eq!(effective_at, DAY + WithdrawalPolicy::LOOSENING_DELAY);
+
+        // Requests made before then still wait out the old timelock
+        assert_eq!(tip_jar.withdrawal_unlock_at(effective_at - 1), effective_at - 1 + 2 * DAY);
+        assert_eq!(tip_jar.withdrawal_unlock_at(effective_at), effective_at);
+        assert!(tip_jar.pending_policy.is_none());
+    }
+
+    #[test]
+    fn treasurer_cap_is_spent_on_request_and_restored_on_cancel() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.owner = Pubkey::new_unique();
+        let mut treasurer = Delegate {
+            tipjar: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            granted_by: tip_jar.owner,
+            roles: Delegate::TREASURER,
+            withdraw_cap: 100,
+            withdrawn: 0,
+            bump: 0,
+        };
+        assert!(tip_jar.is_authorized(&treasurer.delegate, Some(&treasurer), Delegate::TREASURER));
+
+        // Two requests can't together exceed the cap
+        treasurer.record_withdrawal(60).unwrap();
+        assert!(treasurer.record_withdrawal(41).is_err());
+        assert_eq!(treasurer.withdrawn, 60);
+
+        // Cancelling the first request frees its share of the cap again
+        treasurer.restore_withdrawal(60);
+        assert_eq!(treasurer.withdrawn, 0);
+        treasurer.record_withdrawal(100).unwrap();
+
+        // A grant that was reset in the meantime never goes below zero
+        treasurer.withdrawn = 10;
+        treasurer.restore_withdrawal(100);
+        assert_eq!(treasurer.withdrawn, 0);
+
+        // The cap lapses with the grant once the jar changes hands
+        tip_jar.owner = Pubkey::new_unique();
+        assert!(!tip_jar.is_authorized(&treasurer.delegate, Some(&treasurer), Delegate::TREASURER));
+    }
+
+    #[test]
+    fn contribution_totals_drop_refunded_public_pledges() {
+        let mut contribution =
+            Contribution::deserialize(&mut &[0u8; Contribution::LEN][..]).unwrap();
+        contribution.record(50, DAY);
+        assert_eq!((contribution.first_tip_at, contribution.last_tip_at), (DAY, DAY));
+
+        // Only the public part of a pledge is credited
+        let mut tip_jar = campaign_jar();
+        let mut escrow = blank_escrow();
+        tip_jar.record_pledge(&mut escrow, 30, Visibility::Public);
+        contribution.record(30, 2 * DAY);
+        tip_jar.record_pledge(&mut escrow, 20, Visibility::Anonymous);
+        assert_eq!((escrow.pledged, escrow.credited, escrow.credited_count), (50, 30, 1));
+        assert_eq!(contribution.total_amount, 80);
+        assert_eq!(contribution.tip_count, 2);
+        assert_eq!((contribution.first_tip_at, contribution.last_tip_at), (DAY, 2 * DAY));
+
+        // A credited pledge is refunded only together with the contribution it was added to
+        let after = 10 * DAY + 1;
+        assert!(tip_jar.unwind_pledge(&mut escrow, None, after).is_err());
+        assert_eq!(tip_jar.unwind_pledge(&mut escrow, Some(&mut contribution), after).unwrap(), 50);
+        assert_eq!(contribution.total_amount, 50);
+        assert_eq!(contribution.tip_count, 1);
+        assert_eq!((escrow.credited, escrow.credited_count), (0, 0));
+
+        // A contributor who only pledged drops back to no tips at all
+        let mut contribution =
+            Contribution::deserialize(&mut &[0u8; Contribution::LEN][..]).unwrap();
+        let mut tip_jar = campaign_jar();
+        let mut escrow = blank_escrow();
+        for pledge in [10, 20] {
+            tip_jar.record_pledge(&mut escrow, pledge, Visibility::Public);
+            contribution.record(pledge, DAY);
+        }
+        tip_jar.unwind_pledge(&mut escrow, Some(&mut contribution), after).unwrap();
+        assert_eq!((contribution.total_amount, contribution.tip_count), (0, 0));
+    }
+
+    #[test]
+    fn anonymous_tip_record_names_neither_sender_nor_payer() {
+        let tipjar = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let mut record = TipRecord::deserialize(&mut &[0u8; TipRecord::LEN][..]).unwrap();
+
+        let visibility = Visibility::Anonymous;
+        let tip = Tip {
+            sender: visibility.shown_sender(sender),
+            amount: 10,
+            visibility,
+            ..Default::default()
+        };
+        record.fill(tipjar, 3, tip, sender, 255);
+        assert_eq!(record.tip.sender, Pubkey::default());
+        assert_eq!(record.payer, Pubkey::default());
+        assert_eq!((record.tipjar, record.sequence, record.bump), (tipjar, 3, 255));
+
+        // Public records refund the wallet that paid for them
+        let tip = Tip { sender, amount: 10, ..Default::default() };
+        record.fill(tipjar, 4, tip, sender, 255);
+        assert_eq!(record.payer, sender);
+    }
+
+    #[test]
+    fn token_payouts_are_held_to_the_timelock() {
+        let mut tip_jar = blank_tip_jar();
+        tip_jar.withdrawal_policy = timelock_policy(DAY);
+
+        // Direct payouts, tokens included, are off while a timelock is set
+        assert!(tip_jar.require_no_timelock(DAY).is_err());
+        // Proposals wait it out from when they were created
+        assert!(tip_jar.require_timelock_passed(DAY, 2 * DAY - 1).is_err());
+        tip_jar.require_timelock_passed(DAY, 2 * DAY).unwrap();
+
+        // Closing an empty jar pays nothing out, a tracked mint means a sweep
+        assert!(!tip_jar.close_pays_out(0));
+        tip_jar.record_token_amount(Pubkey::new_unique(), 0).unwrap();
+        assert!(tip_jar.close_pays_out(0));
+
+        // Once a shorter timelock takes effect, direct payouts work again
+        tip_jar.set_withdrawal_policy(timelock_policy(0), DAY).unwrap();
+        assert!(tip_jar.require_no_timelock(DAY + WithdrawalPolicy::LOOSENING_DELAY - 1).is_err());
+        tip_jar.require_no_timelock(DAY + WithdrawalPolicy::LOOSENING_DELAY).unwrap();
+    }
+}
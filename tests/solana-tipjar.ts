@@ -1,9 +1,19 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { SolanaTipjar } from "../target/types/solana_tipjar";
-import { PublicKey, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import {
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+  LAMPORTS_PER_SOL,
+} from "@solana/web3.js";
 import { expect } from "chai";
 
+const { TOKEN_PROGRAM_ID, ASSOCIATED_PROGRAM_ID, associatedAddress } = anchor.utils.token;
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
 describe("solana-tipjar", () => {
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
@@ -11,17 +21,173 @@ describe("solana-tipjar", () => {
   const program = anchor.workspace.SolanaTipjar as Program<SolanaTipjar>;
   const owner = provider.wallet;
 
+  let registryPDA: PublicKey;
   let tipjarPDA: PublicKey;
   let tipjarBump: number;
+  let secondTipjarPDA: PublicKey;
+  let configPDA: PublicKey;
+
+  // Jar addresses are derived from the owner's registry index (u32, little endian)
+  const jarIndexSeed = (index: number) => {
+    const seed = Buffer.alloc(4);
+    seed.writeUInt32LE(index);
+    return seed;
+  };
 
   before(async () => {
-    // Generate PDA for tipjar
+    // Generate PDAs for the owner registry and the first tipjar
+    [registryPDA] = await PublicKey.findProgramAddress(
+      [Buffer.from("registry"), owner.publicKey.toBuffer()],
+      program.programId
+    );
     [tipjarPDA, tipjarBump] = await PublicKey.findProgramAddress(
-      [Buffer.from("tipjar"), owner.publicKey.toBuffer()],
+      [Buffer.from("tipjar"), owner.publicKey.toBuffer(), jarIndexSeed(0)],
       program.programId
     );
+    [configPDA] = PublicKey.findProgramAddressSync([Buffer.from("config")], program.programId);
   });
 
+  // Tip records live at ["tip", tipjar, sequence], with the same u32 encoding as jar indexes
+  const tipRecordPDA = (tipjar: PublicKey, sequence: number) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("tip"), tipjar.toBuffer(), jarIndexSeed(sequence)],
+      program.programId
+    )[0];
+
+  const contributionPDA = (tipjar: PublicKey, sender: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("contribution"), tipjar.toBuffer(), sender.toBuffer()],
+      program.programId
+    )[0];
+
+  const fund = async (wallet: PublicKey, lamports: number) => {
+    const signature = await provider.connection.requestAirdrop(wallet, lamports);
+    const latest = await provider.connection.getLatestBlockhash();
+    await provider.connection.confirmTransaction({ signature, ...latest });
+  };
+
+  const expectError = async (pending: Promise<unknown>, code: string) => {
+    try {
+      await pending;
+    } catch (err) {
+      const logs: string[] = err.logs ?? err.simulationResponse?.logs ?? [];
+      expect([String(err), ...logs].join("\n")).to.contain(code);
+      return;
+    }
+    expect.fail(`expected ${code}`);
+  };
+
+  const readOnly = (pubkey: PublicKey) => ({ pubkey, isSigner: false, isWritable: false });
+  const writable = (pubkey: PublicKey) => ({ pubkey, isSigner: false, isWritable: true });
+
+  // Sends a SOL tip, returning the TipRecord it was stored in
+  const sendTip = async (
+    tipjar: PublicKey,
+    sender: Keypair,
+    lamports: number,
+    visibility: { public: {} } | { anonymous: {} },
+    memo: string
+  ) => {
+    const jar = await program.account.tipJar.fetch(tipjar);
+    const tipRecord = tipRecordPDA(tipjar, jar.nextSequence);
+
+    await program.methods
+      .sendTip(new anchor.BN(lamports), visibility, memo, null)
+      .accountsPartial({
+        tipjar,
+        sender: sender.publicKey,
+        config: configPDA,
+        treasury: owner.publicKey,
+        tipRecord,
+        tipHistory: null,
+        allowlistEntry: null,
+        refundEscrow: null,
+        // Anonymous tips are never linked to a contribution
+        contribution: "public" in visibility ? contributionPDA(tipjar, sender.publicKey) : null,
+      })
+      .signers([sender])
+      .rpc();
+
+    return tipRecord;
+  };
+
+  const getTipHistory = (
+    tipjar: PublicKey,
+    order: { oldestFirst: {} } | { newestFirst: {} },
+    cursor: number | null,
+    limit: number,
+    records: PublicKey[]
+  ) =>
+    program.methods
+      .getTipHistory(order, cursor, limit)
+      .accountsPartial({ tipjar, tipHistory: null })
+      .remainingAccounts(records.map(readOnly))
+      .view();
+
+  // Minimal SPL Token setup, so the suite only needs Anchor and web3.js
+  const createMint = async (authority: PublicKey) => {
+    const mint = Keypair.generate();
+    const space = 82;
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(space);
+    const data = Buffer.alloc(35); // InitializeMint2, zero decimals, no freeze authority
+    data.writeUInt8(20, 0);
+    authority.toBuffer().copy(data, 2);
+
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: owner.publicKey,
+          newAccountPubkey: mint.publicKey,
+          lamports,
+          space,
+          programId: TOKEN_PROGRAM_ID,
+        }),
+        new TransactionInstruction({ programId: TOKEN_PROGRAM_ID, keys: [writable(mint.publicKey)], data })
+      ),
+      [mint]
+    );
+
+    return mint.publicKey;
+  };
+
+  const createTokenAccount = async (mint: PublicKey, tokenOwner: PublicKey) => {
+    const address = associatedAddress({ mint, owner: tokenOwner });
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: ASSOCIATED_PROGRAM_ID,
+          keys: [
+            { pubkey: owner.publicKey, isSigner: true, isWritable: true },
+            writable(address),
+            readOnly(tokenOwner),
+            readOnly(mint),
+            readOnly(SystemProgram.programId),
+            readOnly(TOKEN_PROGRAM_ID),
+          ],
+          data: Buffer.from([1]), // CreateIdempotent
+        })
+      )
+    );
+
+    return address;
+  };
+
+  const mintTo = async (mint: PublicKey, destination: PublicKey, amount: number) => {
+    const data = Buffer.concat([Buffer.from([7]), new anchor.BN(amount).toArrayLike(Buffer, "le", 8)]);
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        new TransactionInstruction({
+          programId: TOKEN_PROGRAM_ID,
+          keys: [writable(mint), writable(destination), { pubkey: owner.publicKey, isSigner: true, isWritable: false }],
+          data,
+        })
+      )
+    );
+  };
+
+  const tokenBalance = async (account: PublicKey) =>
+    (await provider.connection.getTokenAccountBalance(account)).value.amount;
+
   it("Initialize TipJar", async () => {
     const description = "My First Tip Jar";
     const category = "Testing";
@@ -30,6 +196,7 @@ describe("solana-tipjar", () => {
     await program.methods
       .initializeTipjar(description, category, goal)
       .accounts({
+        registry: registryPDA,
         tipjar: tipjarPDA,
         user: owner.publicKey,
         systemProgram: SystemProgram.programId,
@@ -42,215 +209,232 @@ describe("solana-tipjar", () => {
     expect(tipjarAccount.goal.toString()).to.equal(goal.toString());
     expect(tipjarAccount.owner.toString()).to.equal(owner.publicKey.toString());
     expect(tipjarAccount.isActive).to.be.true;
+    expect(tipjarAccount.index).to.equal(0);
   });
 
-  // More test cases will be added after seeing state.rs
-});
-
-/**
-import * as anchor from "@coral-xyz/anchor";
-import { Program } from "@coral-xyz/anchor";
-import { Tipjar } from "../target/types/tipjar";
-import { assert } from "chai";
-
-describe("tipjar", () => {
-  const provider = anchor.AnchorProvider.env();
-  anchor.setProvider(provider);
-
-  const program = anchor.workspace.Tipjar as Program<Tipjar>;
-
-  let tipJarPda: anchor.web3.PublicKey;
-  let bump: number;
-  const user = provider.wallet;
-  const systemProgram = anchor.web3.SystemProgram.programId;
-
-  const description = "Support my open-source project";
-  const category = "Development";
-  const goal = new anchor.BN(5_000_000); // 0.005 SOL
-
-  before(async () => {
-    [tipJarPda, bump] = await anchor.web3.PublicKey.findProgramAddress(
-      [Buffer.from("tipjar"), user.publicKey.toBuffer()],
+  it("Initializes a second TipJar for the same owner", async () => {
+    [secondTipjarPDA] = await PublicKey.findProgramAddress(
+      [Buffer.from("tipjar"), owner.publicKey.toBuffer(), jarIndexSeed(1)],
       program.programId
     );
-  });
-
-  it("Initializes a new TipJar", async () => {
-    await program.methods
-      .initializeTipjar(description, category, goal)
-      .accounts({
-        tipjar: tipJarPda,
-        user: user.publicKey,
-        systemProgram,
-      })
-      .signers([])
-      .rpc();
-
-    const tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.equal(tipJarAccount.description, description);
-    assert.equal(tipJarAccount.category, category);
-    assert.ok(tipJarAccount.goal.eq(goal));
-    assert.ok(tipJarAccount.isActive);
-    assert.equal(tipJarAccount.owner.toBase58(), user.publicKey.toBase58());
-  });
-
-  it("Sends a tip to the TipJar", async () => {
-    const tipAmount = new anchor.BN(1_000_000); // 0.001 SOL
-    const memo = "Great work!";
-    const visibility = { public: {} };
-
-    await program.methods
-      .sendTip(tipAmount, visibility, memo)
-      .accounts({
-        tipjar: tipJarPda,
-        sender: user.publicKey,
-        systemProgram,
-      })
-      .signers([])
-      .rpc();
-
-    const tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.ok(tipJarAccount.totalReceived.eq(tipAmount));
-    assert.equal(tipJarAccount.tipsHistory.length, 1);
-    assert.equal(tipJarAccount.tipsHistory[0].memo, memo);
-  });
-
-  it("Retrieves tip statistics", async () => {
-    const tx = await program.methods
-      .getTipStats()
-      .accounts({
-        tipjar: tipJarPda,
-      })
-      .rpc();
-
-    // Since getTipStats emits an event, you can verify it via logs if needed
-    console.log("Tip statistics retrieved successfully.");
-  });
-
-  it("Clears the tip history", async () => {
-    await program.methods
-      .clearTipHistory()
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-      })
-      .signers([])
-      .rpc();
-
-    const tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.equal(tipJarAccount.tipsHistory.length, 0);
-  });
-
-  it("Toggles the TipJar status", async () => {
-    await program.methods
-      .toggleTipjarStatus()
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-      })
-      .signers([])
-      .rpc();
-
-    const tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.isFalse(tipJarAccount.isActive);
-
-    // Toggle back to active
-    await program.methods
-      .toggleTipjarStatus()
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-      })
-      .signers([])
-      .rpc();
-
-    const updatedTipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.isTrue(updatedTipJarAccount.isActive);
-  });
-
-  it("Updates TipJar metadata", async () => {
-    const newDescription = "Updated description";
-    const newCategory = "Updated category";
-    const newGoal = new anchor.BN(10_000_000); // 0.01 SOL
 
     await program.methods
-      .updateTipjar(newDescription, newCategory, newGoal)
+      .initializeTipjar("Stream Jar", "Streaming", new anchor.BN(LAMPORTS_PER_SOL))
       .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
+        registry: registryPDA,
+        tipjar: secondTipjarPDA,
+        user: owner.publicKey,
+        systemProgram: SystemProgram.programId,
       })
-      .signers([])
       .rpc();
 
-    const tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.equal(tipJarAccount.description, newDescription);
-    assert.equal(tipJarAccount.category, newCategory);
-    assert.ok(tipJarAccount.goal.eq(newGoal));
+    const registry = await program.account.ownerRegistry.fetch(registryPDA);
+    expect(registry.nextIndex).to.equal(2);
+    expect(registry.jars.map((jar) => jar.toString())).to.deep.equal([
+      tipjarPDA.toString(),
+      secondTipjarPDA.toString(),
+    ]);
   });
 
-  it("Withdraws tips from the TipJar", async () => {
-    const withdrawAmount = new anchor.BN(500_000); // 0.0005 SOL
+  it("Initializes the protocol config", async () => {
+    const [programData] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_ID
+    );
 
+    // 1% of every SOL tip goes to the owner's wallet as the fee treasury
     await program.methods
-      .withdrawTip(withdrawAmount)
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-        systemProgram,
+      .initializeConfig(100, owner.publicKey)
+      .accountsPartial({
+        config: configPDA,
+        admin: owner.publicKey,
+        program: program.programId,
+        programData,
       })
-      .signers([])
       .rpc();
 
-    const tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.ok(tipJarAccount.totalReceived.lt(goal));
+    const config = await program.account.programConfig.fetch(configPDA);
+    expect(config.feeBps).to.equal(100);
+    expect(config.treasury.toString()).to.equal(owner.publicKey.toString());
   });
 
-  it("Pauses and resumes the TipJar", async () => {
-    // Pause
-    await program.methods
-      .pauseTipjar()
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-      })
-      .signers([])
-      .rpc();
-
-    let tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.isFalse(tipJarAccount.isActive);
-
-    // Resume
-    await program.methods
-      .resumeTipjar()
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-      })
-      .signers([])
-      .rpc();
-
-    tipJarAccount = await program.account.tipJar.fetch(tipJarPda);
-    assert.isTrue(tipJarAccount.isActive);
+  describe("tip history", () => {
+    const supporter = Keypair.generate();
+    let publicRecord: PublicKey;
+    let anonymousRecord: PublicKey;
+
+    before(async () => {
+      await fund(supporter.publicKey, 2 * LAMPORTS_PER_SOL);
+    });
+
+    it("Stores each tip in its own record", async () => {
+      publicRecord = await sendTip(tipjarPDA, supporter, LAMPORTS_PER_SOL / 10, { public: {} }, "Great work");
+      anonymousRecord = await sendTip(tipjarPDA, supporter, LAMPORTS_PER_SOL / 5, { anonymous: {} }, "");
+
+      const record = await program.account.tipRecord.fetch(publicRecord);
+      expect(record.sequence).to.equal(0);
+      expect(record.payer.toString()).to.equal(supporter.publicKey.toString());
+
+      // Neither the sender nor the rent payer of an anonymous tip is stored
+      const anonymous = await program.account.tipRecord.fetch(anonymousRecord);
+      expect(anonymous.sequence).to.equal(1);
+      expect(anonymous.tip.sender.toString()).to.equal(PublicKey.default.toString());
+      expect(anonymous.payer.toString()).to.equal(PublicKey.default.toString());
+
+      // Only the public tip counts toward the supporter's contribution, net of the fee
+      const contribution = await program.account.contribution.fetch(
+        contributionPDA(tipjarPDA, supporter.publicKey)
+      );
+      expect(contribution.tipCount).to.equal(1);
+      expect(contribution.totalAmount.toNumber()).to.equal(99_000_000);
+    });
+
+    it("Pages through the history", async () => {
+      const page = await getTipHistory(tipjarPDA, { oldestFirst: {} }, null, 5, [publicRecord, anonymousRecord]);
+      expect(page.tips.map((entry) => entry.sequence)).to.deep.equal([0, 1]);
+      expect(page.tips[0].tip.sender.toString()).to.equal(supporter.publicKey.toString());
+      expect(page.tips[0].tip.amount.toNumber()).to.equal(99_000_000);
+      expect(page.tips[0].tip.memo).to.equal("Great work");
+      expect(page.nextCursor).to.be.null;
+
+      const newest = await getTipHistory(tipjarPDA, { newestFirst: {} }, null, 1, [anonymousRecord]);
+      expect(newest.tips.map((entry) => entry.sequence)).to.deep.equal([1]);
+      expect(newest.nextCursor).to.equal(0);
+    });
+
+    it("Refunds record rent to the payer when the history is cleared", async () => {
+      const rent = await provider.connection.getBalance(publicRecord);
+      const supporterBefore = await provider.connection.getBalance(supporter.publicKey);
+
+      // Each record is followed by its payer; the caller takes the anonymous record's rent
+      await program.methods
+        .clearTipHistory()
+        .accountsPartial({ tipjar: tipjarPDA, tipHistory: null, delegate: null, authority: owner.publicKey })
+        .remainingAccounts([
+          writable(publicRecord),
+          writable(supporter.publicKey),
+          writable(anonymousRecord),
+          writable(owner.publicKey),
+        ])
+        .rpc();
+
+      expect(await provider.connection.getBalance(supporter.publicKey)).to.equal(supporterBefore + rent);
+      expect(await provider.connection.getAccountInfo(publicRecord)).to.be.null;
+      expect(await provider.connection.getAccountInfo(anonymousRecord)).to.be.null;
+
+      const jar = await program.account.tipJar.fetch(tipjarPDA);
+      expect(jar.totalTipsCount).to.equal(2);
+    });
+
+    it("Skips cleared records, but only at their own address", async () => {
+      const page = await getTipHistory(tipjarPDA, { oldestFirst: {} }, null, 5, [publicRecord, anonymousRecord]);
+      expect(page.tips).to.be.empty;
+      expect(page.nextCursor).to.be.null;
+
+      await expectError(
+        getTipHistory(tipjarPDA, { oldestFirst: {} }, null, 5, [Keypair.generate().publicKey, anonymousRecord]),
+        "InvalidTipRecord"
+      );
+    });
   });
 
-  it("Closes the TipJar", async () => {
-    await program.methods
-      .closeTipjar()
-      .accounts({
-        tipjar: tipJarPda,
-        owner: user.publicKey,
-        systemProgram,
-      })
-      .signers([])
-      .rpc();
-
-    try {
-      await program.account.tipJar.fetch(tipJarPda);
-      assert.fail("TipJar account should be closed");
-    } catch (err) {
-      assert.include(err.message, "Account does not exist");
-    }
+  describe("token tips", () => {
+    const supporter = Keypair.generate();
+    let mint: PublicKey;
+    let supporterTokens: PublicKey;
+    let ownerTokens: PublicKey;
+
+    before(async () => {
+      await fund(supporter.publicKey, LAMPORTS_PER_SOL);
+      mint = await createMint(owner.publicKey);
+      supporterTokens = await createTokenAccount(mint, supporter.publicKey);
+      ownerTokens = await createTokenAccount(mint, owner.publicKey);
+      await mintTo(mint, supporterTokens, 1_000);
+    });
+
+    const sendTokenTip = async (tipjar: PublicKey, amount: number) => {
+      const jar = await program.account.tipJar.fetch(tipjar);
+      const tipRecord = tipRecordPDA(tipjar, jar.nextSequence);
+
+      await program.methods
+        .sendTokenTip(new anchor.BN(amount), { public: {} }, "Tokens!", null)
+        .accountsPartial({
+          tipjar,
+          sender: supporter.publicKey,
+          mint,
+          senderTokenAccount: supporterTokens,
+          vault: associatedAddress({ mint, owner: tipjar }),
+          tipRecord,
+          tipHistory: null,
+          allowlistEntry: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([supporter])
+        .rpc();
+
+      return tipRecord;
+    };
+
+    const withdrawTokenTip = (tipjar: PublicKey, amount: number) =>
+      program.methods
+        .withdrawTokenTip(new anchor.BN(amount))
+        .accountsPartial({
+          tipjar,
+          owner: owner.publicKey,
+          mint,
+          vault: associatedAddress({ mint, owner: tipjar }),
+          destination: ownerTokens,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    it("Tips tokens into the jar's vault and withdraws them", async () => {
+      const vault = associatedAddress({ mint, owner: tipjarPDA });
+      const tipRecord = await sendTokenTip(tipjarPDA, 400);
+
+      expect(await tokenBalance(vault)).to.equal("400");
+      const jar = await program.account.tipJar.fetch(tipjarPDA);
+      expect(jar.tokenTotals).to.have.length(1);
+      expect(jar.tokenTotals[0].mint.toString()).to.equal(mint.toString());
+      expect(jar.tokenTotals[0].totalReceived.toNumber()).to.equal(400);
+
+      const record = await program.account.tipRecord.fetch(tipRecord);
+      expect(record.tip.mint.toString()).to.equal(mint.toString());
+      expect(record.tip.amount.toNumber()).to.equal(400);
+
+      await withdrawTokenTip(tipjarPDA, 150);
+      expect(await tokenBalance(vault)).to.equal("250");
+      expect(await tokenBalance(ownerTokens)).to.equal("150");
+    });
+
+    it("Holds token withdrawals and the close sweep to the timelock", async () => {
+      // The second jar holds no SOL beyond rent, only tokens
+      const vault = associatedAddress({ mint, owner: secondTipjarPDA });
+      await sendTokenTip(secondTipjarPDA, 100);
+
+      const unlimited = new anchor.BN("18446744073709551615");
+      await program.methods
+        .setWithdrawalPolicy({
+          perTxLimit: unlimited,
+          windowLimit: unlimited,
+          windowDuration: new anchor.BN(86_400),
+          timelockDuration: new anchor.BN(3_600),
+        })
+        .accountsPartial({ tipjar: secondTipjarPDA, owner: owner.publicKey })
+        .rpc();
+
+      await expectError(withdrawTokenTip(secondTipjarPDA, 100), "WithdrawalTimelocked");
+      await expectError(
+        program.methods
+          .closeTipjar()
+          .accountsPartial({ tipjar: secondTipjarPDA, registry: registryPDA, owner: owner.publicKey })
+          .remainingAccounts([writable(vault), writable(mint), writable(ownerTokens)])
+          .rpc(),
+        "WithdrawalTimelocked"
+      );
+
+      expect(await tokenBalance(vault)).to.equal("100");
+      expect(await provider.connection.getAccountInfo(secondTipjarPDA)).to.not.be.null;
+    });
   });
 });
-
- */
\ No newline at end of file